@@ -0,0 +1,228 @@
+//! Derive macros for victory-broker tasks, re-exported as `victory_broker::task::io::TaskIo`.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, quote_spanned};
+use syn::{
+    parse_macro_input, spanned::Spanned, Data, DeriveInput, Expr, Fields, GenericArgument, Ident,
+    LitStr, PathArguments, Type,
+};
+
+/// Generates `TaskIo` for a struct whose fields are task inputs and outputs.
+///
+/// - `#[task(name = "...", trigger = <expr>)]` on the struct sets the config name
+///   (defaults to the struct name) and trigger (defaults to `BrokerTaskTrigger::Always`).
+/// - `#[input(topic = "...", mode = latest | new_values)]` fields are read from the inputs
+///   view before each execute. `Option<T>` fields are set to None when the topic can't be read,
+///   any other field fails the execute.
+/// - `#[output(topic = "...")]` fields are written to the outputs view after each execute,
+///   `Option<T>` fields only when Some.
+///
+/// Fields without either attribute are left alone.
+#[proc_macro_derive(TaskIo, attributes(task, input, output))]
+pub fn derive_task_io(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+enum Mode {
+    Latest,
+    NewValues,
+}
+
+enum Role {
+    Input(Mode),
+    Output,
+}
+
+struct TopicField {
+    ident: Ident,
+    ty: Type,
+    topic: LitStr,
+    role: Role,
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new(
+                    input.ident.span(),
+                    "TaskIo requires a struct with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new(
+                input.ident.span(),
+                "TaskIo can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut name = LitStr::new(&input.ident.to_string(), input.ident.span());
+    let mut trigger: Option<Expr> = None;
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("task")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                name = meta.value()?.parse()?;
+            } else if meta.path.is_ident("trigger") {
+                trigger = Some(meta.value()?.parse()?);
+            } else {
+                return Err(meta.error("Expected `name` or `trigger`"));
+            }
+            Ok(())
+        })?;
+    }
+
+    let mut topic_fields = Vec::new();
+    for field in fields {
+        for attr in &field.attrs {
+            let is_input = attr.path().is_ident("input");
+            if !is_input && !attr.path().is_ident("output") {
+                continue;
+            }
+            let mut topic: Option<LitStr> = None;
+            let mut mode = Mode::Latest;
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("topic") {
+                    topic = Some(meta.value()?.parse()?);
+                } else if is_input && meta.path.is_ident("mode") {
+                    let value: Ident = meta.value()?.parse()?;
+                    mode = match value.to_string().as_str() {
+                        "latest" => Mode::Latest,
+                        "new_values" => Mode::NewValues,
+                        _ => {
+                            return Err(syn::Error::new(
+                                value.span(),
+                                "Expected `latest` or `new_values`",
+                            ))
+                        }
+                    };
+                } else if is_input {
+                    return Err(meta.error("Expected `topic` or `mode`"));
+                } else {
+                    return Err(meta.error("Expected `topic`"));
+                }
+                Ok(())
+            })?;
+            let topic = topic.ok_or_else(|| syn::Error::new(attr.span(), "Missing `topic`"))?;
+            topic_fields.push(TopicField {
+                ident: field.ident.clone().unwrap(),
+                ty: field.ty.clone(),
+                topic,
+                role: if is_input {
+                    Role::Input(mode)
+                } else {
+                    Role::Output
+                },
+            });
+        }
+    }
+
+    let private = quote!(::victory_broker::task::io::__private);
+    let trigger = trigger
+        .map(|t| quote!(#t))
+        .unwrap_or_else(|| quote!(::victory_broker::task::trigger::BrokerTaskTrigger::Always));
+
+    let mut subscriptions = Vec::new();
+    let mut reads = Vec::new();
+    let mut writes = Vec::new();
+    for field in &topic_fields {
+        let TopicField {
+            ident, ty, topic, ..
+        } = field;
+        let inner = option_inner(ty);
+        let value_ty = inner.unwrap_or(ty);
+        let typed_topic = quote_spanned! {ty.span()=>
+            #private::TypedTopic::<#value_ty>::from(#topic)
+        };
+        match &field.role {
+            Role::Input(mode) => {
+                let constructor = match mode {
+                    Mode::Latest => quote!(new_latest),
+                    Mode::NewValues => quote!(new_updates_only),
+                };
+                subscriptions.push(quote! {
+                    .with_subscription(
+                        ::victory_broker::task::subscription::BrokerTaskSubscription::#constructor(
+                            &#private::TopicKey::from_str(#topic),
+                        ),
+                    )
+                });
+                let read = if inner.is_some() {
+                    quote!(inputs.get(&#typed_topic).ok())
+                } else {
+                    let message = format!("Failed to read input `{}` from {{}}: {{}}", ident);
+                    quote! {
+                        inputs
+                            .get(&#typed_topic)
+                            .map_err(|e| #private::anyhow::anyhow!(#message, #topic, e))?
+                    }
+                };
+                reads.push(quote!(self.#ident = #read;));
+            }
+            Role::Output => {
+                let topic_key = quote!(#private::TopicKey::from_str(#topic));
+                writes.push(if inner.is_some() {
+                    quote! {
+                        if let Some(value) = &self.#ident {
+                            outputs.add_latest(&#topic_key, value)?;
+                        }
+                    }
+                } else {
+                    quote!(outputs.add_latest(&#topic_key, &self.#ident)?;)
+                });
+            }
+        }
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::victory_broker::task::io::TaskIo for #ident #ty_generics #where_clause {
+            fn task_config(&self) -> ::victory_broker::task::config::BrokerTaskConfig {
+                ::victory_broker::task::config::BrokerTaskConfig::new(#name)
+                    .with_trigger(#trigger)
+                    #(#subscriptions)*
+            }
+
+            fn read_inputs(
+                &mut self,
+                inputs: &#private::DataView,
+            ) -> ::std::result::Result<(), #private::anyhow::Error> {
+                #(#reads)*
+                Ok(())
+            }
+
+            fn write_outputs(
+                &self,
+                outputs: &mut #private::DataView,
+            ) -> ::std::result::Result<(), #private::anyhow::Error> {
+                #(#writes)*
+                Ok(())
+            }
+        }
+    })
+}
+
+/// `T` if `ty` is written as `Option<T>`
+fn option_inner(ty: &Type) -> Option<&Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    if segment.ident != "Option" {
+        return None;
+    }
+    let PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return None;
+    };
+    match args.args.first()? {
+        GenericArgument::Type(inner) => Some(inner),
+        _ => None,
+    }
+}
@@ -378,8 +378,9 @@ where
 
 #[cfg(test)]
 mod broker_tests {
-    use std::sync::{Arc, Mutex};
+    use std::sync::Arc;
 
+    use tokio::sync::Mutex;
     use victory_data_store::topics::TopicKey;
     use victory_wtf::Timespan;
 
@@ -396,8 +397,8 @@ mod broker_tests {
     /// 2. Add an adapter to the broker
     /// 3. Add a task to the adapter
 
-    #[test]
-    fn test_tick() {
+    #[test(tokio::test)]
+    async fn test_tick() {
         let mut broker = Broker::new(MockBrokerCommander::new());
         let mut adapter = MockBrokerAdapter::new();
 
@@ -418,11 +419,11 @@ mod broker_tests {
         adapter.new_tasks.push(task_b);
 
         broker.adapters.insert(0, Arc::new(Mutex::new(adapter)));
-        broker.tick(Timespan::new_secs(0.1)).unwrap();
+        broker.tick(Timespan::new_secs(0.1)).await.unwrap();
         assert_eq!(broker.task_states[&0].status, BrokerTaskStatus::Completed);
         assert_eq!(broker.task_states[&1].status, BrokerTaskStatus::Idle);
 
-        broker.tick(Timespan::new_secs(0.1)).unwrap();
+        broker.tick(Timespan::new_secs(0.1)).await.unwrap();
         assert_eq!(broker.task_states[&0].status, BrokerTaskStatus::Completed);
         assert_eq!(broker.task_states[&1].status, BrokerTaskStatus::Completed);
     }
@@ -522,4 +523,31 @@ mod broker_tests {
         // Check that the task state was added to the task_states map
         assert_eq!(broker.task_states.len(), 1);
     }
+
+    /// A glob subscription pulls inputs from every matching topic
+    #[test]
+    fn test_get_task_inputs_glob() {
+        let broker = Broker::new(MockBrokerCommander::new());
+        {
+            let mut datastore = broker.datastore.lock().unwrap();
+            for topic in ["robots/a/pose/x", "robots/b/pose/x", "robots/b/twist/x"] {
+                datastore
+                    .add_primitive(&TopicKey::from_str(topic), Timepoint::zero(), 1.into())
+                    .unwrap();
+            }
+        }
+
+        let mut task = BrokerTaskConfig::new_with_id(0, "test_task");
+        let subscription =
+            BrokerTaskSubscription::new_latest(&TopicKey::from_str("robots/*/pose"));
+        assert!(subscription.matches(&TopicKey::from_str("robots/c/pose/y")));
+        task.subscriptions.push(subscription);
+
+        let inputs = broker
+            .get_task_inputs(&task, &BrokerTaskState::new(0))
+            .unwrap();
+        let mut names: Vec<String> = inputs.maps.keys().map(|k| k.display_name()).collect();
+        names.sort();
+        assert_eq!(names, vec!["robots/a/pose/x", "robots/b/pose/x"]);
+    }
 }
@@ -2,7 +2,8 @@ use std::collections::HashMap;
 
 use info::BrokerNodeInfo;
 use log::{debug, info};
-use victory_data_store::database::view::DataView;
+use remap::TopicRemap;
+use victory_data_store::{database::view::DataView, topics::TopicKeyProvider};
 use victory_wtf::Timepoint;
 
 use crate::{
@@ -10,6 +11,7 @@ use crate::{
 };
 
 pub mod info;
+pub mod remap;
 
 pub type NodeID = u32;
 
@@ -19,6 +21,8 @@ pub struct BrokerNode {
     pub view: DataView,
     pub task_handles: HashMap<BrokerTaskID, BrokerTaskHandle>,
     pub task_configs: HashMap<BrokerTaskID, BrokerTaskConfig>,
+    /// Namespace and remap rules between task topics and broker topics
+    pub remap: TopicRemap,
 }
 
 impl BrokerNode {
@@ -28,10 +32,17 @@ impl BrokerNode {
             adapter,
             view: DataView::new(),
             task_handles: HashMap::new(),
-            task_configs: HashMap::new()
+            task_configs: HashMap::new(),
+            remap: TopicRemap::new(),
         }
     }
 
+    /// Set before adding tasks, their subscriptions are remapped as they are added
+    pub fn with_remap(mut self, remap: TopicRemap) -> Self {
+        self.remap = remap;
+        self
+    }
+
     pub fn init(&mut self) -> Result<(), anyhow::Error> {
         for task_handle in self.task_handles.values_mut() {
             task_handle.lock().unwrap().init()?;
@@ -40,7 +51,10 @@ impl BrokerNode {
     }
 
     pub fn add_task(&mut self, task_handle: BrokerTaskHandle) -> Result<(), anyhow::Error> {
-        let task_config = task_handle.lock().unwrap().get_config();
+        let mut task_config = task_handle.lock().unwrap().get_config();
+        for subscription in task_config.subscriptions.iter_mut() {
+            subscription.topic_query = self.remap.to_global(&subscription.topic_query).handle();
+        }
         info!(
             "Node {:?} - Adding task {:?} to node {:?}",
             self.info.node_id, task_config.task_id, self.info.node_id
@@ -102,7 +116,12 @@ impl BrokerNode {
             let results = task_handle.lock().unwrap().on_execute(&inputs, &time)?;
 
             // 4. Send the results back to the adapter using send outputs
-            let outputs = results.get_all_datapoints();
+            let mut outputs = results.get_all_datapoints();
+            if !self.remap.is_identity() {
+                for datapoint in outputs.iter_mut() {
+                    datapoint.topic = self.remap.to_global(&datapoint.topic).handle();
+                }
+            }
             for chunk in outputs.chunks(32) {
                 debug!(
                     "Node {:?} - Sending {:?} outputs for task {:?}",
@@ -141,6 +160,82 @@ impl BrokerNode {
             }
         }
 
+        // Tasks see their own topic names
+        if !self.remap.is_identity() {
+            inputs.maps = inputs
+                .maps
+                .into_iter()
+                .map(|(topic, mut datapoint)| {
+                    let local = self.remap.to_local(&topic);
+                    datapoint.topic = local.handle();
+                    (local, datapoint)
+                })
+                .collect();
+        }
+
         Ok(inputs)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use victory_data_store::{datapoints::Datapoint, primitives::Primitives, topics::TopicKey};
+
+    use crate::{
+        adapters::{channel::ChannelBrokerAdapter, BrokerAdapter},
+        task::example::task_accumulate::TaskAccumulate,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_node_remap() {
+        let (broker_side, node_side) = ChannelBrokerAdapter::new_pair();
+        let remap = TopicRemap::new()
+            .with_namespace("robot_3")
+            .unwrap()
+            .with_rule("accumulate/gain -> /shared/gain".parse().unwrap());
+        let mut node = BrokerNode::new(BrokerNodeInfo::new("robot_3"), node_side).with_remap(remap);
+        node.add_task(Arc::new(Mutex::new(TaskAccumulate::default())))
+            .unwrap();
+
+        let mut broker_side = broker_side.try_lock().unwrap();
+        let tasks = broker_side.get_new_tasks().unwrap();
+        let queries: Vec<String> = tasks[0]
+            .subscriptions
+            .iter()
+            .map(|s| s.topic_query.display_name())
+            .collect();
+        assert_eq!(queries, vec!["robot_3/accumulate/input", "shared/gain"]);
+
+        let input = |topic: &str, value: f64| {
+            Datapoint::new(
+                &TopicKey::from_str(topic),
+                Timepoint::zero(),
+                Primitives::Float(value),
+            )
+        };
+        broker_side
+            .send_inputs(&vec![
+                input("robot_3/accumulate/input", 2.0),
+                input("shared/gain", 3.0),
+            ])
+            .unwrap();
+        broker_side
+            .send_execute(&tasks[0], &BrokerTime::default())
+            .unwrap();
+        node.tick().unwrap();
+
+        let outputs = broker_side.recv_outputs().unwrap();
+        let total = outputs
+            .iter()
+            .find(|d| d.topic.display_name() == "robot_3/accumulate/total")
+            .unwrap();
+        assert_eq!(total.value, Primitives::Float(6.0));
+        assert!(outputs
+            .iter()
+            .all(|d| d.topic.display_name().starts_with("robot_3/")));
+    }
+}
@@ -0,0 +1,187 @@
+use std::str::FromStr;
+
+use victory_data_store::topics::{parse::TopicParseError, TopicKey};
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum RemapError {
+    #[error("Expected a remap rule like 'from -> to', got '{0}'")]
+    MissingArrow(String),
+    #[error(transparent)]
+    Topic(#[from] TopicParseError),
+}
+
+/// Renames a topic and everything below it, like a ROS remap
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemapRule {
+    pub from: TopicKey,
+    pub to: TopicKey,
+    /// `to` was written with a leading `/`, so it is not placed under the namespace
+    pub absolute: bool,
+}
+
+impl RemapRule {
+    pub fn new(from: &str, to: &str) -> Result<RemapRule, RemapError> {
+        Ok(RemapRule {
+            from: TopicKey::parse(from)?,
+            to: TopicKey::parse(to)?,
+            absolute: to.starts_with('/'),
+        })
+    }
+}
+
+impl FromStr for RemapRule {
+    type Err = RemapError;
+
+    /// Parse `camera/raw -> cam_front/raw`
+    fn from_str(rule: &str) -> Result<Self, Self::Err> {
+        let (from, to) = rule
+            .split_once("->")
+            .ok_or_else(|| RemapError::MissingArrow(rule.to_string()))?;
+        RemapRule::new(from.trim(), to.trim())
+    }
+}
+
+/// Maps the topics a task uses to the topics on the broker, so the same task can run on
+/// several nodes. Remap rules are applied first, then the namespace is prepended.
+#[derive(Debug, Clone)]
+pub struct TopicRemap {
+    pub namespace: TopicKey,
+    pub rules: Vec<RemapRule>,
+}
+
+impl Default for TopicRemap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TopicRemap {
+    pub fn new() -> TopicRemap {
+        TopicRemap {
+            namespace: TopicKey::empty(),
+            rules: Vec::new(),
+        }
+    }
+
+    pub fn with_namespace(mut self, namespace: &str) -> Result<TopicRemap, RemapError> {
+        self.namespace = TopicKey::parse(namespace)?;
+        Ok(self)
+    }
+
+    pub fn with_rule(mut self, rule: RemapRule) -> TopicRemap {
+        self.rules.push(rule);
+        self
+    }
+
+    pub fn is_identity(&self) -> bool {
+        self.namespace.sections.is_empty() && self.rules.is_empty()
+    }
+
+    fn global_target(&self, rule: &RemapRule) -> TopicKey {
+        if rule.absolute {
+            rule.to.clone()
+        } else {
+            rule.to.add_prefix(self.namespace.clone())
+        }
+    }
+
+    /// Task topic to broker topic. The longest matching rule wins.
+    pub fn to_global(&self, topic: &TopicKey) -> TopicKey {
+        let matched = self
+            .rules
+            .iter()
+            .filter_map(|rule| Some((rule, topic.remove_prefix(rule.from.clone())?)))
+            .max_by_key(|(rule, _)| rule.from.sections.len());
+        match matched {
+            Some((rule, rest)) => self.global_target(rule).add_suffix_owned(rest),
+            None => topic.add_prefix(self.namespace.clone()),
+        }
+    }
+
+    /// Broker topic to task topic. Topics outside the namespace and every rule are unchanged.
+    pub fn to_local(&self, topic: &TopicKey) -> TopicKey {
+        let matched = self
+            .rules
+            .iter()
+            .filter_map(|rule| {
+                let target = self.global_target(rule);
+                let rest = topic.remove_prefix(target.clone())?;
+                Some((rule, target.sections.len(), rest))
+            })
+            .max_by_key(|(_, len, _)| *len);
+        match matched {
+            Some((rule, _, rest)) => rule.from.add_suffix(&rest),
+            None => topic
+                .remove_prefix(self.namespace.clone())
+                .unwrap_or_else(|| topic.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn remap() -> TopicRemap {
+        TopicRemap::new()
+            .with_namespace("robot_3")
+            .unwrap()
+            .with_rule("camera/raw -> cam_front/raw".parse().unwrap())
+            .with_rule("camera/raw/info -> cam_front/calibration".parse().unwrap())
+            .with_rule("clock -> /clock".parse().unwrap())
+    }
+
+    #[test]
+    fn test_remap_to_global() {
+        let remap = remap();
+        let global = |topic: &str| remap.to_global(&topic.into()).display_name();
+        assert_eq!(global("odom/pose"), "robot_3/odom/pose");
+        assert_eq!(global("camera/raw"), "robot_3/cam_front/raw");
+        assert_eq!(global("camera/raw/width"), "robot_3/cam_front/raw/width");
+        assert_eq!(
+            global("camera/raw/info/fx"),
+            "robot_3/cam_front/calibration/fx"
+        );
+        assert_eq!(global("clock"), "clock");
+        assert!(TopicRemap::new().is_identity());
+    }
+
+    #[test]
+    fn test_remap_to_local() {
+        let remap = remap();
+        let local = |topic: &str| remap.to_local(&topic.into()).display_name();
+        assert_eq!(local("robot_3/odom/pose"), "odom/pose");
+        assert_eq!(local("robot_3/cam_front/raw/width"), "camera/raw/width");
+        assert_eq!(
+            local("robot_3/cam_front/calibration/fx"),
+            "camera/raw/info/fx"
+        );
+        assert_eq!(local("clock"), "clock");
+        assert_eq!(local("robot_4/odom/pose"), "robot_4/odom/pose");
+
+        for topic in [
+            "odom/pose",
+            "camera/raw/width",
+            "camera/raw/info/fx",
+            "clock",
+        ] {
+            let key = TopicKey::from_str(topic);
+            assert_eq!(remap.to_local(&remap.to_global(&key)), key);
+        }
+    }
+
+    #[test]
+    fn test_remap_rule_parse() {
+        let rule: RemapRule = " a/b ->  /c ".parse().unwrap();
+        assert_eq!(rule.from, TopicKey::from_str("a/b"));
+        assert!(rule.absolute);
+        assert!(matches!(
+            "a/b".parse::<RemapRule>(),
+            Err(RemapError::MissingArrow(_))
+        ));
+        assert!(matches!(
+            "a//b -> c".parse::<RemapRule>(),
+            Err(RemapError::Topic(_))
+        ));
+    }
+}
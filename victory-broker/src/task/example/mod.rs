@@ -1,3 +1,4 @@
+pub mod task_accumulate;
 pub mod task_math;
 pub mod task_printer;
 pub mod task_ticker;
@@ -0,0 +1,89 @@
+use anyhow::Result;
+
+use crate::{
+    broker::time::BrokerTime,
+    task::io::{IoTask, TaskIo},
+};
+
+/// Sums every new value published to `accumulate/input`, with config and
+/// input/output handling generated by `#[derive(TaskIo)]`
+#[derive(Debug, Default, TaskIo)]
+#[task(name = "TaskAccumulate")]
+pub struct TaskAccumulate {
+    #[input(topic = "accumulate/input", mode = new_values)]
+    pub input: Option<f64>,
+    #[input(topic = "accumulate/gain")]
+    pub gain: f64,
+    #[output(topic = "accumulate/total")]
+    pub total: f64,
+    #[output(topic = "accumulate/count")]
+    pub count: u64,
+}
+
+impl IoTask for TaskAccumulate {
+    fn execute(&mut self, _timing: &BrokerTime) -> Result<()> {
+        if let Some(input) = self.input {
+            self.total += input * self.gain;
+            self.count += 1;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use victory_data_store::{
+        database::view::DataView,
+        topics::{typed::TypedTopic, TopicKey},
+    };
+
+    use crate::task::{subscription::SubscriptionMode, trigger::BrokerTaskTrigger, BrokerTask};
+
+    use super::*;
+
+    #[test]
+    fn test_task_accumulate_config() {
+        let config = TaskAccumulate::default().get_config();
+        assert_eq!(config.name, "TaskAccumulate");
+        assert_eq!(config.trigger, BrokerTaskTrigger::Always);
+        assert_eq!(config.subscriptions.len(), 2);
+        assert_eq!(
+            config.subscriptions[0].topic_query.display_name(),
+            "accumulate/input"
+        );
+        assert_eq!(config.subscriptions[0].mode, SubscriptionMode::NewValues);
+        assert_eq!(config.subscriptions[1].mode, SubscriptionMode::Latest);
+    }
+
+    #[test]
+    fn test_task_accumulate_execute() {
+        let mut task = TaskAccumulate::default();
+        let total: TypedTopic<f64> = "accumulate/total".into();
+        let count: TypedTopic<u64> = "accumulate/count".into();
+
+        let mut inputs = DataView::new();
+        inputs
+            .add_latest(&TopicKey::from_str("accumulate/input"), 1.5)
+            .unwrap();
+        inputs
+            .add_latest(&TopicKey::from_str("accumulate/gain"), 2.0)
+            .unwrap();
+        let outputs = task.on_execute(&inputs, &BrokerTime::default()).unwrap();
+        assert_eq!(outputs.get(&total).unwrap(), 3.0);
+        assert_eq!(outputs.get(&count).unwrap(), 1);
+
+        // No new input leaves the total alone
+        let mut inputs = DataView::new();
+        inputs
+            .add_latest(&TopicKey::from_str("accumulate/gain"), 2.0)
+            .unwrap();
+        let outputs = task.on_execute(&inputs, &BrokerTime::default()).unwrap();
+        assert!(task.input.is_none());
+        assert_eq!(outputs.get(&total).unwrap(), 3.0);
+        assert_eq!(outputs.get(&count).unwrap(), 1);
+
+        // Missing a required input fails the execute
+        let result = task.on_execute(&DataView::new(), &BrokerTime::default());
+        assert!(result.is_err());
+    }
+}
@@ -1,6 +1,9 @@
 use crate::{broker::time::BrokerTime, task::{config::BrokerTaskConfig, trigger::BrokerTaskTrigger, BrokerTask}};
 use anyhow::{Error, Result};
-use victory_data_store::{database::view::DataView, topics::TopicKey};
+use victory_data_store::{
+    database::view::DataView,
+    topics::{typed::TypedTopic, TopicKey},
+};
 
 pub enum Operation {
     Add,
@@ -10,9 +13,9 @@ pub enum Operation {
 }
 
 pub struct TaskMath {
-    pub input_topic_a: TopicKey,
-    pub input_topic_b: TopicKey,
-    pub output_topic: TopicKey,
+    pub input_topic_a: TypedTopic<u64>,
+    pub input_topic_b: TypedTopic<u64>,
+    pub output_topic: TypedTopic<u64>,
     pub operation: Operation,
 }
 
@@ -24,9 +27,9 @@ impl TaskMath {
         operation: Operation,
     ) -> Self {
         Self {
-            input_topic_a,
-            input_topic_b,
-            output_topic,
+            input_topic_a: TypedTopic::new(&input_topic_a),
+            input_topic_b: TypedTopic::new(&input_topic_b),
+            output_topic: TypedTopic::new(&output_topic),
             operation,
         }
     }
@@ -45,10 +48,10 @@ impl BrokerTask for TaskMath {
         let mut outputs = DataView::new();
 
         let value_a = inputs
-            .get_latest::<_, u64>(&self.input_topic_a)
+            .get(&self.input_topic_a)
             .map_err(|_| anyhow::anyhow!("No value found for input_topic_a"))?;
         let value_b = inputs
-            .get_latest::<_, u64>(&self.input_topic_b)
+            .get(&self.input_topic_b)
             .map_err(|_| anyhow::anyhow!("No value found for input_topic_b"))?;
 
         let result = match self.operation {
@@ -63,7 +66,7 @@ impl BrokerTask for TaskMath {
             }
         };
 
-        outputs.add_latest(&self.output_topic, result);
+        outputs.set(&self.output_topic, result)?;
         Ok(outputs)
     }
 }
@@ -91,7 +94,8 @@ mod tests {
         inputs.add_latest(&input_topic_b, 5u64);
 
         let outputs = task_math.on_execute(&inputs, &BrokerTime::default()).unwrap();
-        assert_eq!(outputs.get_latest::<_, u64>(&output_topic).unwrap(), 15u64);
+        let output_topic: TypedTopic<u64> = TypedTopic::new(&output_topic);
+        assert_eq!(outputs.get(&output_topic).unwrap(), 15u64);
     }
 
     #[test]
@@ -1,16 +1,19 @@
-use victory_data_store::{database::view::DataView, topics::TopicKey};
+use victory_data_store::{
+    database::view::DataView,
+    topics::{typed::TypedTopic, TopicKey},
+};
 
 use crate::{broker::time::BrokerTime, task::{config::BrokerTaskConfig, trigger::BrokerTaskTrigger, BrokerTask}};
 
 pub struct TaskTicker {
-    pub publish_topic: TopicKey,
+    pub publish_topic: TypedTopic<u64>,
     tick_value: u64,
 }
 
 impl TaskTicker {
     pub fn new(publish_topic: TopicKey) -> Self {
         Self {
-            publish_topic,
+            publish_topic: TypedTopic::new(&publish_topic),
             tick_value: 0,
         }
     }
@@ -28,7 +31,7 @@ impl BrokerTask for TaskTicker {
     ) -> Result<victory_data_store::database::view::DataView, anyhow::Error> {
         let mut outputs = DataView::new();
         self.tick_value += 1;
-        outputs.add_latest(&self.publish_topic, self.tick_value);
+        outputs.set(&self.publish_topic, self.tick_value)?;
         Ok(outputs)
     }
     
@@ -0,0 +1,53 @@
+use anyhow::Error;
+use victory_data_store::database::view::DataView;
+
+use crate::broker::time::BrokerTime;
+
+use super::{config::BrokerTaskConfig, BrokerTask};
+
+pub use victory_broker_derive::TaskIo;
+
+/// Config and input/output plumbing of a task, usually generated by `#[derive(TaskIo)]`
+pub trait TaskIo {
+    fn task_config(&self) -> BrokerTaskConfig;
+    /// Copy the subscribed topics from `inputs` into the input fields
+    fn read_inputs(&mut self, inputs: &DataView) -> Result<(), Error>;
+    /// Write the output fields to `outputs`
+    fn write_outputs(&self, outputs: &mut DataView) -> Result<(), Error>;
+}
+
+/// A task that works on its own input and output fields. Every `IoTask` is a `BrokerTask`,
+/// with inputs read before `execute` and outputs collected after it.
+pub trait IoTask: TaskIo + Send {
+    fn init(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+    fn execute(&mut self, timing: &BrokerTime) -> Result<(), Error>;
+}
+
+impl<T: IoTask> BrokerTask for T {
+    fn init(&mut self) -> Result<(), Error> {
+        IoTask::init(self)
+    }
+
+    fn get_config(&self) -> BrokerTaskConfig {
+        self.task_config()
+    }
+
+    fn on_execute(&mut self, inputs: &DataView, timing: &BrokerTime) -> Result<DataView, Error> {
+        self.read_inputs(inputs)?;
+        self.execute(timing)?;
+        let mut outputs = DataView::new();
+        self.write_outputs(&mut outputs)?;
+        Ok(outputs)
+    }
+}
+
+#[doc(hidden)]
+pub mod __private {
+    pub use anyhow;
+    pub use victory_data_store::{
+        database::view::DataView,
+        topics::{typed::TypedTopic, TopicKey},
+    };
+}
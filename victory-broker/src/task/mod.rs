@@ -6,6 +6,7 @@ use victory_data_store::database::view::DataView;
 use crate::broker::time::BrokerTime;
 
 pub mod example;
+pub mod io;
 pub mod state;
 pub mod subscription;
 pub mod trigger;
@@ -1,5 +1,5 @@
 use serde::{Deserialize, Serialize};
-use victory_data_store::topics::{TopicKeyHandle, TopicKeyProvider};
+use victory_data_store::topics::{pattern::TopicPattern, TopicKeyHandle, TopicKeyProvider};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum SubscriptionMode {
@@ -9,6 +9,7 @@ pub enum SubscriptionMode {
 
 /// A subscription for a broker task
 /// - topic_query: The topic key to subscribe to. Will be used to filter the messages that the task will receive.
+///   May contain `*` (one section) and `**` (any number of sections) wildcards.
 /// - mode: The mode of the subscription. Either pull all latest values or only new values.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BrokerTaskSubscription {
@@ -30,4 +31,13 @@ impl BrokerTaskSubscription {
             mode: SubscriptionMode::NewValues,
         }
     }
+
+    /// Compiled matcher for `topic_query`
+    pub fn pattern(&self) -> TopicPattern {
+        TopicPattern::new(&self.topic_query)
+    }
+
+    pub fn matches<T: TopicKeyProvider>(&self, topic: &T) -> bool {
+        self.pattern().matches(topic)
+    }
 }
@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+
+use divan::counter::ItemsCount;
+use divan::AllocProfiler;
+use victory_data_store::database::Datastore;
+use victory_data_store::topics::pattern::TopicPattern;
+use victory_data_store::topics::tree::TopicTree;
+use victory_data_store::topics::{TopicKey, TopicKeyHandle, TopicKeyProvider};
+
+#[global_allocator]
+static ALLOC: AllocProfiler = AllocProfiler::system();
+fn main() {
+    // Run registered benchmarks.
+    divan::main();
+}
+
+const SIZES: &[usize] = &[1_000, 10_000, 50_000];
+
+/// `count` leaf topics spread over 10 robots, like the fields written by `add_struct`
+fn leaf_topics(count: usize) -> Vec<TopicKeyHandle> {
+    (0..count)
+        .map(|i| {
+            TopicKey::from_str(&format!(
+                "robots/{}/sensors/{}/field_{}",
+                i % 10,
+                (i / 10) % 100,
+                i
+            ))
+            .handle()
+        })
+        .collect()
+}
+
+fn query() -> TopicKey {
+    TopicKey::from_str("robots/3/sensors/42")
+}
+
+/// The previous approach: scan every bucket and check `is_child_of`
+#[divan::bench(args = SIZES)]
+fn bench_subtree_linear_scan(bencher: divan::Bencher, size: usize) {
+    let map: HashMap<TopicKeyHandle, usize> = leaf_topics(size)
+        .into_iter()
+        .enumerate()
+        .map(|(i, k)| (k, i))
+        .collect();
+    let query = query();
+
+    bencher.counter(ItemsCount::new(size)).bench(|| {
+        map.iter()
+            .filter(|(k, _)| k.is_child_of(&query))
+            .map(|(_, v)| *v)
+            .collect::<Vec<usize>>()
+    });
+}
+
+#[divan::bench(args = SIZES)]
+fn bench_subtree_topic_tree(bencher: divan::Bencher, size: usize) {
+    let mut tree = TopicTree::new();
+    for (i, topic) in leaf_topics(size).into_iter().enumerate() {
+        tree.insert(&topic, i);
+    }
+    let query = query();
+
+    bencher.counter(ItemsCount::new(size)).bench(|| {
+        tree.get_subtree(&query)
+            .into_iter()
+            .map(|(_, v)| *v)
+            .collect::<Vec<usize>>()
+    });
+}
+
+#[divan::bench(args = SIZES)]
+fn bench_pattern_linear_scan(bencher: divan::Bencher, size: usize) {
+    let map: HashMap<TopicKeyHandle, usize> = leaf_topics(size)
+        .into_iter()
+        .enumerate()
+        .map(|(i, k)| (k, i))
+        .collect();
+    let pattern = TopicPattern::from("robots/*/sensors/42");
+
+    bencher.counter(ItemsCount::new(size)).bench(|| {
+        map.iter()
+            .filter(|(k, _)| pattern.matches(*k))
+            .map(|(_, v)| *v)
+            .collect::<Vec<usize>>()
+    });
+}
+
+#[divan::bench(args = SIZES)]
+fn bench_pattern_topic_tree(bencher: divan::Bencher, size: usize) {
+    let mut tree = TopicTree::new();
+    for (i, topic) in leaf_topics(size).into_iter().enumerate() {
+        tree.insert(&topic, i);
+    }
+    let pattern = TopicPattern::from("robots/*/sensors/42");
+
+    bencher.counter(ItemsCount::new(size)).bench(|| {
+        tree.get_matching(&pattern)
+            .into_iter()
+            .map(|(_, v)| *v)
+            .collect::<Vec<usize>>()
+    });
+}
+
+#[divan::bench(args = SIZES)]
+fn bench_datastore_get_buckets_matching(bencher: divan::Bencher, size: usize) {
+    let mut datastore = Datastore::new();
+    for topic in leaf_topics(size) {
+        datastore.create_bucket(&topic);
+    }
+    let query = query();
+
+    bencher
+        .counter(ItemsCount::new(size))
+        .bench(|| datastore.get_buckets_matching(&query).unwrap());
+}
@@ -0,0 +1,51 @@
+use std::collections::BTreeMap;
+
+use divan::counter::ItemsCount;
+use divan::AllocProfiler;
+use victory_data_store::database::Datastore;
+use victory_data_store::primitives::serde::serialize::to_map;
+use victory_data_store::test_util::BigState;
+use victory_data_store::topics::{TopicKey, TopicKeyHandle, TopicKeyProvider};
+use victory_wtf::Timepoint;
+
+#[global_allocator]
+static ALLOC: AllocProfiler = AllocProfiler::system();
+fn main() {
+    // Run registered benchmarks.
+    divan::main();
+}
+
+/// Lookups in a `DatapointMap` shaped map, each comparison goes through `TopicKey::cmp`
+#[divan::bench]
+fn bench_btree_lookup(bencher: divan::Bencher) {
+    let topics: Vec<TopicKeyHandle> = (0..1000)
+        .map(|i| TopicKey::from_str(&format!("robots/{}/sensors/field_{}", i % 10, i)).handle())
+        .collect();
+    let map: BTreeMap<TopicKeyHandle, usize> = topics
+        .iter()
+        .cloned()
+        .enumerate()
+        .map(|(i, k)| (k, i))
+        .collect();
+
+    bencher
+        .counter(ItemsCount::new(topics.len()))
+        .bench(|| topics.iter().map(|t| map[t]).sum::<usize>());
+}
+
+#[divan::bench]
+fn bench_to_map(bencher: divan::Bencher) {
+    let state = BigState::new();
+    bencher.bench(|| to_map(&state).unwrap());
+}
+
+#[divan::bench]
+fn bench_get_struct(bencher: divan::Bencher) {
+    let topic = TopicKey::from_str("robot/state");
+    let mut datastore = Datastore::new();
+    datastore
+        .add_struct(&topic, Timepoint::now(), BigState::new())
+        .unwrap();
+
+    bencher.bench(|| datastore.get_struct::<_, BigState>(&topic).unwrap());
+}
@@ -0,0 +1,67 @@
+use serde::{Deserialize, Serialize};
+
+use crate::primitives::Primitives;
+
+/// Decides whether a new value is stored, given the bucket's latest value
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum DedupPolicy {
+    /// Only store values that differ from the latest value
+    #[default]
+    DedupOnChange,
+    /// Store every value, e.g. for heartbeat topics
+    KeepAll,
+    /// Only store numeric values that moved more than the band from the latest value.
+    /// Non-numeric values fall back to `DedupOnChange`.
+    Deadband(f64),
+}
+
+impl DedupPolicy {
+    pub fn should_insert(&self, latest: Option<&Primitives>, value: &Primitives) -> bool {
+        let Some(latest) = latest else {
+            // Always insert the first value
+            return true;
+        };
+        match self {
+            DedupPolicy::KeepAll => true,
+            DedupPolicy::DedupOnChange => latest != value,
+            DedupPolicy::Deadband(band) => match (as_f64(latest), as_f64(value)) {
+                (Some(latest), Some(value)) => (value - latest).abs() > *band,
+                _ => latest != value,
+            },
+        }
+    }
+}
+
+fn as_f64(value: &Primitives) -> Option<f64> {
+    match value {
+        Primitives::Float(v) => Some(*v),
+        Primitives::Integer(v) => Some(*v as f64),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dedup_policy() {
+        let one = Primitives::Float(1.0);
+        assert!(DedupPolicy::DedupOnChange.should_insert(None, &one));
+        assert!(!DedupPolicy::DedupOnChange.should_insert(Some(&one), &one));
+        assert!(DedupPolicy::KeepAll.should_insert(Some(&one), &one));
+
+        let deadband = DedupPolicy::Deadband(0.5);
+        assert!(!deadband.should_insert(Some(&one), &Primitives::Float(1.4)));
+        assert!(deadband.should_insert(Some(&one), &Primitives::Float(1.6)));
+        assert!(deadband.should_insert(Some(&one), &Primitives::Integer(2)));
+        assert!(!deadband.should_insert(
+            Some(&Primitives::Text("a".to_string())),
+            &Primitives::Text("a".to_string())
+        ));
+        assert!(deadband.should_insert(
+            Some(&Primitives::Text("a".to_string())),
+            &Primitives::Text("b".to_string())
+        ));
+    }
+}
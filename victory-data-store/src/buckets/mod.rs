@@ -7,8 +7,20 @@ use log::{debug, info, trace, warn};
 use serde::{Deserialize, Serialize};
 use victory_wtf::Timepoint;
 
+pub mod dedup;
+pub mod reorder;
+pub mod stats;
+
+use dedup::DedupPolicy;
+use reorder::{CollisionPolicy, InsertOutcome, ReorderPolicy};
+
 use crate::{
-    database::retention::RetentionPolicy,
+    database::{
+        downsample::{downsample, Downsample},
+        range::TimeRange,
+        retention::RetentionPolicy,
+        sample::{lerp_primitives, SampleMode},
+    },
     datapoints::Datapoint,
     primitives::Primitives,
     topics::{TopicKeyHandle, TopicKeyProvider},
@@ -20,6 +32,19 @@ pub struct Bucket {
     pub topic: TopicKeyHandle,
     pub values: BTreeMap<Timepoint, Datapoint>,
     retention: RetentionPolicy,
+    /// Total number of datapoints dropped by the retention policy
+    #[serde(default)]
+    evicted_count: usize,
+    #[serde(default)]
+    dedup: DedupPolicy,
+    /// Total number of writes discarded by the dedup or reorder policy
+    #[serde(default)]
+    rejected_count: usize,
+    #[serde(default)]
+    reorder: ReorderPolicy,
+    /// Total number of late datapoints accepted within the reorder window
+    #[serde(default)]
+    late_count: usize,
 }
 
 pub type BucketHandle = Arc<RwLock<Bucket>>;
@@ -30,13 +55,79 @@ impl Bucket {
             topic: topic.handle(),
             values: BTreeMap::new(),
             retention: RetentionPolicy::default(),
+            evicted_count: 0,
+            dedup: DedupPolicy::default(),
+            rejected_count: 0,
+            reorder: ReorderPolicy::default(),
+            late_count: 0,
         }))
     }
     #[tracing::instrument(skip_all)]
     pub fn set_retention(&mut self, retention: RetentionPolicy) {
-       
         self.retention = retention;
     }
+
+    pub fn get_retention(&self) -> &RetentionPolicy {
+        &self.retention
+    }
+
+    pub fn set_dedup(&mut self, dedup: DedupPolicy) {
+        self.dedup = dedup;
+    }
+
+    pub fn get_dedup(&self) -> DedupPolicy {
+        self.dedup
+    }
+
+    /// Total number of writes discarded by this bucket's dedup or reorder policy
+    pub fn get_rejected_count(&self) -> usize {
+        self.rejected_count
+    }
+
+    pub fn set_reorder(&mut self, reorder: ReorderPolicy) {
+        self.reorder = reorder;
+    }
+
+    pub fn get_reorder(&self) -> &ReorderPolicy {
+        &self.reorder
+    }
+
+    /// Total number of late datapoints accepted by this bucket
+    pub fn get_late_count(&self) -> usize {
+        self.late_count
+    }
+
+    /// Total number of datapoints evicted from this bucket by its retention policy
+    pub fn get_evicted_count(&self) -> usize {
+        self.evicted_count
+    }
+
+    /// Drop all datapoints older than `now - max_age`.
+    /// Returns the number of datapoints evicted.
+    #[tracing::instrument(skip_all)]
+    pub fn apply_retention(&mut self, now: &Timepoint) -> usize {
+        let max_age = match &self.retention.max_age {
+            Some(max_age) => max_age.clone(),
+            None => return 0,
+        };
+
+        let cutoff = now.clone() - max_age;
+        let before = self.values.len();
+        self.values = self.values.split_off(&cutoff);
+        let evicted = before - self.values.len();
+
+        if evicted > 0 {
+            debug!(
+                "Evicted {} datapoints older than {:.3}s from {:?}",
+                evicted,
+                cutoff.secs(),
+                self.topic.display_name()
+            );
+            self.evicted_count += evicted;
+        }
+        evicted
+    }
+
     #[tracing::instrument(skip_all)]
     pub fn add_primitive(&mut self, time: Timepoint, value: Primitives) -> Result<usize, String> {
         let data_point = Datapoint {
@@ -48,11 +139,47 @@ impl Bucket {
         self.add_datapoint(data_point)
     }
 
+    /// Insert a datapoint, returning 1 if it was stored and 0 if it was rejected
     #[tracing::instrument(skip_all)]
     pub fn add_datapoint(&mut self, data_point: Datapoint) -> Result<usize, String> {
+        self.insert(data_point)
+            .map(|outcome| outcome.is_stored() as usize)
+    }
+
+    /// Insert a datapoint according to the bucket's dedup and reorder policies
+    #[tracing::instrument(skip_all)]
+    pub fn insert(&mut self, data_point: Datapoint) -> Result<InsertOutcome, String> {
         trace!("Adding datapoint: {}", self.topic);
+        self.make_room();
+
+        let outcome = self.classify(&data_point)?;
+        match outcome {
+            InsertOutcome::Rejected => self.rejected_count += 1,
+            _ => {
+                if outcome == InsertOutcome::Late {
+                    self.late_count += 1;
+                }
+                self.store(data_point);
+            }
+        }
+        Ok(outcome)
+    }
 
-        // Check to see if we have stored too many datapoints
+    /// Insert a datapoint that was already accepted once, like one replayed from the WAL.
+    /// Dedup and reorder checks are skipped, retention still applies.
+    /// Returns false if the bucket already held the same value at that time.
+    #[tracing::instrument(skip_all)]
+    pub fn restore(&mut self, data_point: Datapoint) -> bool {
+        if self.values.get(&data_point.time).map(|dp| &dp.value) == Some(&data_point.value) {
+            return false;
+        }
+        self.make_room();
+        self.store(data_point);
+        true
+    }
+
+    /// Drop the oldest half of `max_rows` once the bucket is full
+    fn make_room(&mut self) {
         if let Some(max_rows) = self.retention.max_rows {
             if self.values.len() >= max_rows {
                 // Drop max_rows / 2 datapoints
@@ -66,20 +193,75 @@ impl Bucket {
                 for _ in 0..drop_count {
                     self.values.pop_first();
                 }
+                self.evicted_count += drop_count;
             }
         }
+    }
+
+    fn store(&mut self, data_point: Datapoint) {
+        let time = data_point.time.clone();
+        self.values.insert(time, data_point);
 
-        // Only insert if value changed from previous
-        let should_insert = match self.get_latest_datapoint() {
-            Some(prev_value) => prev_value.value != data_point.value,
-            None => true, // Always insert first value
+        // Age out anything older than max_age relative to the newest datapoint
+        if let Some(latest) = self.get_latest_datapoint().map(|d| d.time.clone()) {
+            self.apply_retention(&latest);
+        }
+    }
+
+    /// Decide how a datapoint would be inserted without modifying the bucket
+    fn classify(&self, data_point: &Datapoint) -> Result<InsertOutcome, String> {
+        let time = &data_point.time;
+        if let Some(existing) = self.values.get(time) {
+            // Retransmits of the same value are never an error
+            if existing.value == data_point.value {
+                return Ok(InsertOutcome::Rejected);
+            }
+            return match self.reorder.collision {
+                CollisionPolicy::Overwrite => Ok(InsertOutcome::Overwritten),
+                CollisionPolicy::KeepFirst => Ok(InsertOutcome::Rejected),
+                CollisionPolicy::Error => Err(format!(
+                    "Datapoint at {:.3}s already exists in {}",
+                    time.secs(),
+                    self.topic.display_name()
+                )),
+            };
+        }
+
+        let latest = match self.values.keys().next_back() {
+            Some(latest) if time < latest => latest,
+            _ => {
+                let insert = self
+                    .dedup
+                    .should_insert(self.get_latest_value(), &data_point.value);
+                return Ok(if insert {
+                    InsertOutcome::Appended
+                } else {
+                    InsertOutcome::Rejected
+                });
+            }
         };
 
-        if should_insert {
-            self.values.insert(data_point.time.clone(), data_point);
-            return Ok(1);
+        if let Some(window) = &self.reorder.window {
+            if *time < latest.clone() - window.clone() {
+                debug!(
+                    "Rejected datapoint {:.3}s behind the latest in {:?}",
+                    latest.secs() - time.secs(),
+                    self.topic.display_name()
+                );
+                return Ok(InsertOutcome::Rejected);
+            }
         }
-        Ok(0)
+        // Dedup against the value in effect at that time, not the latest one
+        let previous = self
+            .values
+            .range(..time.clone())
+            .next_back()
+            .map(|(_, v)| &v.value);
+        Ok(if self.dedup.should_insert(previous, &data_point.value) {
+            InsertOutcome::Late
+        } else {
+            InsertOutcome::Rejected
+        })
     }
 
     /// Update a datapoint in the bucket without notifying listeners
@@ -143,6 +325,39 @@ impl Bucket {
         before.or_else(|| self.get_latest_datapoint())
     }
 
+    /// Value of the bucket at `time`, or None if `time` is before the first datapoint.
+    /// Linear mode interpolates numeric values between the surrounding datapoints.
+    #[tracing::instrument(skip_all)]
+    pub fn sample(&self, time: &Timepoint, mode: SampleMode) -> Option<Primitives> {
+        let (prev_time, prev) = self.values.range(..=time.clone()).next_back()?;
+        if mode == SampleMode::HoldLast || prev_time == time {
+            return Some(prev.value.clone());
+        }
+
+        match self.values.range(time.clone()..).next() {
+            Some((next_time, next)) => {
+                let span = (next_time.ns() - prev_time.ns()) as f64;
+                let alpha = (time.ns() - prev_time.ns()) as f64 / span;
+                lerp_primitives(&prev.value, &next.value, alpha).or(Some(prev.value.clone()))
+            }
+            None => Some(prev.value.clone()),
+        }
+    }
+
+    /// Reduce the numeric datapoints in `[range.start, range.end)` to `bins` time bins
+    #[tracing::instrument(skip_all)]
+    pub fn downsample(&self, range: &TimeRange, bins: usize, mode: Downsample) -> Vec<Datapoint> {
+        if range.is_empty() {
+            return Vec::new();
+        }
+        let values = self
+            .values
+            .range(range.start.clone()..range.end.clone())
+            .map(|(_, v)| v)
+            .collect();
+        downsample(values, range, bins, mode)
+    }
+
     #[tracing::instrument(skip_all)]
     /// Get all datapoints after or at a given time
     pub fn get_data_points_after(&self, time: &Timepoint) -> Vec<&Datapoint> {
@@ -153,15 +368,38 @@ impl Bucket {
     pub fn get_data_points_before(&self, time: &Timepoint) -> Vec<&Datapoint> {
         self.values.range(..time.clone()).map(|(_, v)| v).collect()
     }
+
+    /// Get datapoints in `[range.start, range.end)`, honouring the range's order and limit
+    #[tracing::instrument(skip_all)]
+    pub fn get_data_points_range(&self, range: &TimeRange) -> Vec<&Datapoint> {
+        if range.is_empty() {
+            return Vec::new();
+        }
+        let values = self
+            .values
+            .range(range.start.clone()..range.end.clone())
+            .map(|(_, v)| v);
+        let limit = range.limit.unwrap_or(usize::MAX);
+        if range.reverse {
+            values.rev().take(limit).collect()
+        } else {
+            values.take(limit).collect()
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
 
-    use victory_wtf::{Timecode, Timepoint};
+    use victory_wtf::{Timecode, Timepoint, Timespan};
 
     use crate::{
-        buckets::Bucket,
+        buckets::{
+            dedup::DedupPolicy,
+            reorder::{CollisionPolicy, InsertOutcome, ReorderPolicy},
+            Bucket,
+        },
+        database::retention::RetentionPolicy,
         datapoints::Datapoint,
         primitives::Primitives,
         topics::{TopicKey, TopicKeyProvider},
@@ -262,4 +500,116 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_bucket_retention_max_age() {
+        let topic = TopicKey::from_str("test/topic");
+        let bucket = Bucket::new(&topic);
+        let mut bucket = bucket.write().unwrap();
+        bucket.set_retention(RetentionPolicy {
+            max_age: Some(Timespan::new_secs(2.0)),
+            max_rows: None,
+        });
+
+        for i in 0..10 {
+            bucket
+                .add_primitive(Timepoint::new_secs(i as f64), Primitives::Integer(i))
+                .unwrap();
+        }
+
+        // Only 7s, 8s and 9s are within 2s of the latest datapoint
+        let times: Vec<f64> = bucket.values.keys().map(|t| t.secs()).collect();
+        assert_eq!(times, vec![7.0, 8.0, 9.0]);
+        assert_eq!(bucket.get_evicted_count(), 7);
+
+        // Sweeping with a later time ages out the rest
+        let evicted = bucket.apply_retention(&Timepoint::new_secs(10.5));
+        assert_eq!(evicted, 2);
+        assert_eq!(bucket.values.len(), 1);
+        assert_eq!(bucket.get_evicted_count(), 9);
+    }
+
+    #[test]
+    fn test_bucket_dedup_policy() {
+        let topic = TopicKey::from_str("test/heartbeat");
+        let bucket = Bucket::new(&topic);
+        let mut bucket = bucket.write().unwrap();
+
+        let add = |bucket: &mut Bucket, secs: f64, value: f64| {
+            bucket
+                .add_primitive(Timepoint::new_secs(secs), Primitives::Float(value))
+                .unwrap()
+        };
+
+        // Repeated values are dropped by default
+        assert_eq!(add(&mut bucket, 0.0, 1.0), 1);
+        assert_eq!(add(&mut bucket, 1.0, 1.0), 0);
+        assert_eq!(bucket.get_rejected_count(), 1);
+
+        bucket.set_dedup(DedupPolicy::KeepAll);
+        assert_eq!(add(&mut bucket, 2.0, 1.0), 1);
+
+        bucket.set_dedup(DedupPolicy::Deadband(0.5));
+        assert_eq!(add(&mut bucket, 3.0, 1.2), 0);
+        assert_eq!(add(&mut bucket, 4.0, 1.8), 1);
+        assert_eq!(bucket.values.len(), 3);
+        assert_eq!(bucket.get_rejected_count(), 2);
+    }
+
+    #[test]
+    fn test_bucket_late_datapoints() {
+        let topic = TopicKey::from_str("test/remote");
+        let bucket = Bucket::new(&topic);
+        let mut bucket = bucket.write().unwrap();
+        bucket.set_reorder(ReorderPolicy::with_window(Timespan::new_secs(5.0)));
+
+        let insert = |bucket: &mut Bucket, secs: f64, value: i64| {
+            bucket.insert(Datapoint::new(
+                &topic,
+                Timepoint::new_secs(secs),
+                Primitives::Integer(value),
+            ))
+        };
+
+        assert_eq!(insert(&mut bucket, 10.0, 1), Ok(InsertOutcome::Appended));
+        assert_eq!(insert(&mut bucket, 11.0, 2), Ok(InsertOutcome::Appended));
+        // Same value as the latest, but not as the value in effect at 8s
+        assert_eq!(insert(&mut bucket, 8.0, 2), Ok(InsertOutcome::Late));
+        // Same value as the one before it
+        assert_eq!(insert(&mut bucket, 9.0, 2), Ok(InsertOutcome::Rejected));
+        // Outside the reorder window
+        assert_eq!(insert(&mut bucket, 5.0, 3), Ok(InsertOutcome::Rejected));
+
+        let times: Vec<f64> = bucket.values.keys().map(|t| t.secs()).collect();
+        assert_eq!(times, vec![8.0, 10.0, 11.0]);
+        assert_eq!(bucket.get_late_count(), 1);
+        assert_eq!(bucket.get_rejected_count(), 2);
+    }
+
+    #[test]
+    fn test_bucket_collision_policy() {
+        let topic = TopicKey::from_str("test/remote");
+        let bucket = Bucket::new(&topic);
+        let mut bucket = bucket.write().unwrap();
+        let insert = |bucket: &mut Bucket, value: i64| {
+            bucket.insert(Datapoint::new(
+                &topic,
+                Timepoint::new_secs(1.0),
+                Primitives::Integer(value),
+            ))
+        };
+
+        assert_eq!(insert(&mut bucket, 1), Ok(InsertOutcome::Appended));
+        assert_eq!(insert(&mut bucket, 2), Ok(InsertOutcome::Overwritten));
+        assert_eq!(bucket.get_latest_value(), Some(&Primitives::Integer(2)));
+
+        bucket.set_reorder(ReorderPolicy::default().with_collision(CollisionPolicy::KeepFirst));
+        assert_eq!(insert(&mut bucket, 3), Ok(InsertOutcome::Rejected));
+        assert_eq!(bucket.get_latest_value(), Some(&Primitives::Integer(2)));
+
+        bucket.set_reorder(ReorderPolicy::default().with_collision(CollisionPolicy::Error));
+        assert!(insert(&mut bucket, 3).is_err());
+        // Resending the stored value is not a collision
+        assert_eq!(insert(&mut bucket, 2), Ok(InsertOutcome::Rejected));
+    }
 }
@@ -0,0 +1,58 @@
+use serde::{Deserialize, Serialize};
+use victory_wtf::Timespan;
+
+/// What to do when a datapoint arrives for a time that already holds a different value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum CollisionPolicy {
+    #[default]
+    Overwrite,
+    KeepFirst,
+    /// Reject the write with an error
+    Error,
+}
+
+/// How a bucket treats datapoints older than its latest datapoint
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ReorderPolicy {
+    /// Late datapoints further than this behind the latest datapoint are rejected.
+    /// None accepts late data of any age.
+    pub window: Option<Timespan>,
+    pub collision: CollisionPolicy,
+}
+
+impl ReorderPolicy {
+    pub fn with_window(window: Timespan) -> ReorderPolicy {
+        ReorderPolicy {
+            window: Some(window),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_collision(mut self, collision: CollisionPolicy) -> ReorderPolicy {
+        self.collision = collision;
+        self
+    }
+}
+
+/// Result of inserting a datapoint into a bucket
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InsertOutcome {
+    /// Newer than every stored datapoint
+    Appended,
+    /// Older than the latest datapoint but within the reorder window
+    Late,
+    /// Replaced a different value at the same time
+    Overwritten,
+    /// Not stored, either unchanged, a kept-first collision or outside the reorder window
+    Rejected,
+}
+
+impl InsertOutcome {
+    pub fn is_stored(&self) -> bool {
+        *self != InsertOutcome::Rejected
+    }
+
+    pub fn is_in_order(&self) -> bool {
+        *self == InsertOutcome::Appended
+    }
+}
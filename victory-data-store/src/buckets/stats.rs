@@ -0,0 +1,121 @@
+use std::mem::size_of;
+
+use memuse::DynamicUsage;
+use serde::{Deserialize, Serialize};
+use victory_wtf::Timepoint;
+
+use crate::{datapoints::Datapoint, topics::TopicKeyHandle};
+
+use super::Bucket;
+
+/// Snapshot of a bucket's contents and write history
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BucketStats {
+    pub topic: TopicKeyHandle,
+    pub rows: usize,
+    pub first: Option<Timepoint>,
+    pub last: Option<Timepoint>,
+    /// Mean datapoints per second between the first and last datapoint
+    pub rate_hz: Option<f64>,
+    /// Standard deviation of the interval between datapoints, in seconds
+    pub jitter_secs: Option<f64>,
+    /// Estimated bytes held by the stored datapoints, excluding map overhead
+    pub memory_bytes: usize,
+    /// Writes discarded by the dedup or reorder policy
+    pub rejected: usize,
+    /// Datapoints dropped by the retention policy
+    pub evicted: usize,
+    /// Late datapoints accepted within the reorder window
+    pub late: usize,
+}
+
+impl Bucket {
+    #[tracing::instrument(skip_all)]
+    pub fn stats(&self) -> BucketStats {
+        let first = self.values.keys().next().cloned();
+        let last = self.values.keys().next_back().cloned();
+
+        let intervals: Vec<f64> = self
+            .values
+            .keys()
+            .zip(self.values.keys().skip(1))
+            .map(|(a, b)| (b.ns() - a.ns()) as f64 / 1e9)
+            .collect();
+        let (rate_hz, jitter_secs) = if intervals.is_empty() {
+            (None, None)
+        } else {
+            let mean = intervals.iter().sum::<f64>() / intervals.len() as f64;
+            let variance =
+                intervals.iter().map(|i| (i - mean).powi(2)).sum::<f64>() / intervals.len() as f64;
+            let rate = if mean > 0.0 { Some(1.0 / mean) } else { None };
+            (rate, Some(variance.sqrt()))
+        };
+
+        let memory_bytes = self.values.len() * (size_of::<Timepoint>() + size_of::<Datapoint>())
+            + self
+                .values
+                .values()
+                .map(|d| d.value.dynamic_usage())
+                .sum::<usize>();
+
+        BucketStats {
+            topic: self.topic.clone(),
+            rows: self.values.len(),
+            first,
+            last,
+            rate_hz,
+            jitter_secs,
+            memory_bytes,
+            rejected: self.rejected_count,
+            evicted: self.evicted_count,
+            late: self.late_count,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{primitives::Primitives, topics::TopicKey};
+
+    use super::*;
+
+    #[test]
+    fn test_bucket_stats() {
+        let topic = TopicKey::from_str("test/topic");
+        let bucket = Bucket::new(&topic);
+        let mut bucket = bucket.write().unwrap();
+        assert_eq!(bucket.stats().rows, 0);
+        assert!(bucket.stats().rate_hz.is_none());
+
+        // 10 Hz with one interval stretched to 0.2s
+        let times = [0.0, 0.1, 0.2, 0.4, 0.5];
+        for (i, secs) in times.iter().enumerate() {
+            bucket
+                .add_primitive(Timepoint::new_secs(*secs), Primitives::Integer(i as i64))
+                .unwrap();
+        }
+        bucket
+            .add_primitive(Timepoint::new_secs(0.6), Primitives::Integer(4))
+            .unwrap();
+
+        let stats = bucket.stats();
+        assert_eq!(stats.rows, 5);
+        assert_eq!(stats.first, Some(Timepoint::new_secs(0.0)));
+        assert_eq!(stats.last, Some(Timepoint::new_secs(0.5)));
+        assert!((stats.rate_hz.unwrap() - 8.0).abs() < 1e-9);
+        assert!((stats.jitter_secs.unwrap() - 0.0433).abs() < 1e-3);
+        assert_eq!(stats.rejected, 1);
+        assert_eq!(
+            stats.memory_bytes,
+            5 * (size_of::<Timepoint>() + size_of::<Datapoint>())
+        );
+
+        bucket
+            .add_primitive(
+                Timepoint::new_secs(1.0),
+                Primitives::Text("hello".to_string()),
+            )
+            .unwrap();
+        assert!(bucket.stats().memory_bytes >= 6 * size_of::<Datapoint>() + 5);
+    }
+}
@@ -0,0 +1,235 @@
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+use victory_wtf::Timepoint;
+
+use crate::{datapoints::Datapoint, primitives::Primitives, topics::TopicKeyProvider};
+
+use super::{range::TimeRange, Datastore, DatastoreError};
+
+/// How the numeric datapoints in each time bin are reduced
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Downsample {
+    /// Smallest value of each bin, at the time it occurred
+    Min,
+    /// Largest value of each bin, at the time it occurred
+    Max,
+    /// Mean of each bin as a Float, at the start of the bin
+    Mean,
+    /// Last value of each bin
+    Last,
+    /// Smallest and largest value of each bin in time order, so spikes stay visible
+    MinMax,
+    /// Largest-Triangle-Three-Buckets, picks the datapoints that best preserve the shape
+    Lttb,
+}
+
+fn numeric(value: &Primitives) -> Option<f64> {
+    match value {
+        Primitives::Float(v) => Some(*v),
+        Primitives::Integer(v) => Some(*v as f64),
+        _ => None,
+    }
+}
+
+/// Reduce time ordered `datapoints` within `range` to `bins` equal width bins.
+/// Non-numeric datapoints are skipped.
+pub(crate) fn downsample(
+    datapoints: Vec<&Datapoint>,
+    range: &TimeRange,
+    bins: usize,
+    mode: Downsample,
+) -> Vec<Datapoint> {
+    let points: Vec<(&Datapoint, f64)> = datapoints
+        .into_iter()
+        .filter_map(|d| numeric(&d.value).map(|v| (d, v)))
+        .collect();
+    if bins == 0 || points.is_empty() || range.is_empty() {
+        return Vec::new();
+    }
+    if mode == Downsample::Lttb {
+        return lttb(&points, bins);
+    }
+
+    let start = range.start.ns();
+    let width = (range.end.ns() - start).div_ceil(bins as u128).max(1);
+    let bin_of = |d: &Datapoint| (d.time.ns() - start) / width;
+
+    let mut out = Vec::new();
+    for bin in points.chunk_by(|a, b| bin_of(a.0) == bin_of(b.0)) {
+        let min = bin.iter().min_by(|a, b| a.1.total_cmp(&b.1)).unwrap();
+        let max = bin.iter().max_by(|a, b| a.1.total_cmp(&b.1)).unwrap();
+        match mode {
+            Downsample::Min => out.push(min.0.clone()),
+            Downsample::Max => out.push(max.0.clone()),
+            Downsample::Last => out.push(bin.last().unwrap().0.clone()),
+            Downsample::Mean => {
+                let mean = bin.iter().map(|(_, v)| v).sum::<f64>() / bin.len() as f64;
+                out.push(Datapoint {
+                    topic: bin[0].0.topic.clone(),
+                    time: Timepoint::new_ns(start + bin_of(bin[0].0) * width),
+                    value: Primitives::Float(mean),
+                });
+            }
+            Downsample::MinMax => {
+                let (first, second) = if min.0.time <= max.0.time {
+                    (min, max)
+                } else {
+                    (max, min)
+                };
+                out.push(first.0.clone());
+                if first.0.time != second.0.time {
+                    out.push(second.0.clone());
+                }
+            }
+            Downsample::Lttb => unreachable!(),
+        }
+    }
+    out
+}
+
+/// Largest-Triangle-Three-Buckets: keep the first and last points, then from each bucket
+/// pick the point forming the largest triangle with the previous pick and the next bucket's mean
+fn lttb(points: &[(&Datapoint, f64)], threshold: usize) -> Vec<Datapoint> {
+    let n = points.len();
+    if threshold >= n {
+        return points.iter().map(|(d, _)| (*d).clone()).collect();
+    }
+    if threshold < 3 {
+        return [points[0], points[n - 1]]
+            .iter()
+            .take(threshold)
+            .map(|(d, _)| (*d).clone())
+            .collect();
+    }
+
+    // Relative times keep the precision that absolute nanoseconds lose as f64
+    let origin = points[0].0.time.ns();
+    let x = |i: usize| (points[i].0.time.ns() - origin) as f64;
+    let y = |i: usize| points[i].1;
+
+    let every = (n - 2) as f64 / (threshold - 2) as f64;
+    let mut out = Vec::with_capacity(threshold);
+    out.push(points[0].0.clone());
+    let mut a = 0;
+    for i in 0..threshold - 2 {
+        let avg_start = ((i + 1) as f64 * every) as usize + 1;
+        let avg_end = (((i + 2) as f64 * every) as usize + 1).min(n);
+        let count = (avg_end - avg_start) as f64;
+        let avg_x = (avg_start..avg_end).map(x).sum::<f64>() / count;
+        let avg_y = (avg_start..avg_end).map(y).sum::<f64>() / count;
+
+        let range_start = (i as f64 * every) as usize + 1;
+        let range_end = ((i + 1) as f64 * every) as usize + 1;
+        let area =
+            |j: usize| ((x(a) - avg_x) * (y(j) - y(a)) - (x(a) - x(j)) * (avg_y - y(a))).abs();
+        let picked = (range_start..range_end)
+            .max_by(|j, k| area(*j).total_cmp(&area(*k)))
+            .unwrap();
+        out.push(points[picked].0.clone());
+        a = picked;
+    }
+    out.push(points[n - 1].0.clone());
+    out
+}
+
+impl Datastore {
+    /// Downsample every bucket matching `query` over `range` into `bins` time bins.
+    /// Results are grouped by topic, sorted by display name, each in time order.
+    #[instrument(skip_all)]
+    pub fn downsample<T: TopicKeyProvider>(
+        &self,
+        query: &T,
+        range: &TimeRange,
+        bins: usize,
+        mode: Downsample,
+    ) -> Result<Vec<Datapoint>, DatastoreError> {
+        if bins == 0 {
+            return Err(DatastoreError::Generic(
+                "Downsample bin count must be greater than zero".to_string(),
+            ));
+        }
+
+        let mut buckets = self.get_buckets_matching(query)?;
+        buckets.sort_by_key(|b| b.read().unwrap().topic.display_name());
+        Ok(buckets
+            .iter()
+            .flat_map(|b| b.read().unwrap().downsample(range, bins, mode))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::topics::TopicKey;
+
+    use super::*;
+
+    /// 1 kHz ramp with a single spike at 0.5s
+    fn ramp(datastore: &mut Datastore, topic: &TopicKey) {
+        let datapoints = (0..1000)
+            .map(|i| {
+                let value = if i == 500 { 100.0 } else { i as f64 / 1000.0 };
+                Datapoint::new(topic, Timepoint::new_ms(i as f64), value.into())
+            })
+            .collect();
+        datastore.add_datapoints(datapoints);
+    }
+
+    #[test]
+    fn test_downsample_bins() {
+        let mut datastore = Datastore::new();
+        let topic: TopicKey = "imu/accel/x".into();
+        ramp(&mut datastore, &topic);
+        let range = TimeRange::new(Timepoint::zero(), Timepoint::new_secs(1.0));
+        let query = |mode| datastore.downsample(&topic, &range, 10, mode).unwrap();
+
+        let max = query(Downsample::Max);
+        assert_eq!(max.len(), 10);
+        assert_eq!(max[5].value, Primitives::Float(100.0));
+        assert_eq!(max[5].time, Timepoint::new_ms(500.0));
+
+        let min = query(Downsample::Min);
+        assert_eq!(min[0].value, Primitives::Float(0.0));
+
+        let last = query(Downsample::Last);
+        assert_eq!(last[9].time, Timepoint::new_ms(999.0));
+
+        let mean = query(Downsample::Mean);
+        assert_eq!(mean[1].time, Timepoint::new_ms(100.0));
+        match mean[1].value {
+            Primitives::Float(v) => assert!((v - 0.1495).abs() < 1e-9),
+            ref other => panic!("Expected a Float mean, got {:?}", other),
+        }
+
+        // Min and max per bin, except where they land on the same datapoint
+        let minmax = query(Downsample::MinMax);
+        assert_eq!(minmax.len(), 20);
+        assert!(minmax.windows(2).all(|w| w[0].time < w[1].time));
+
+        assert!(datastore
+            .downsample(&topic, &range, 0, Downsample::Max)
+            .is_err());
+    }
+
+    #[test]
+    fn test_downsample_lttb() {
+        let mut datastore = Datastore::new();
+        let topic: TopicKey = "imu/accel/x".into();
+        ramp(&mut datastore, &topic);
+        let range = TimeRange::new(Timepoint::zero(), Timepoint::new_secs(1.0));
+
+        let result = datastore
+            .downsample(&topic, &range, 50, Downsample::Lttb)
+            .unwrap();
+        assert_eq!(result.len(), 50);
+        assert_eq!(result[0].time, Timepoint::zero());
+        assert_eq!(result[49].time, Timepoint::new_ms(999.0));
+        assert!(result.iter().any(|d| d.value == Primitives::Float(100.0)));
+
+        // Fewer datapoints than requested returns them all
+        let result = datastore
+            .downsample(&topic, &range, 5000, Downsample::Lttb)
+            .unwrap();
+        assert_eq!(result.len(), 1000);
+    }
+}
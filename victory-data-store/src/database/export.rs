@@ -0,0 +1,410 @@
+use std::{
+    collections::{BTreeSet, HashSet},
+    fs::File,
+    path::Path,
+    sync::Arc,
+};
+
+use arrow::{
+    array::{
+        ArrayRef, BinaryBuilder, BooleanBuilder, Float64Builder, Int64Builder, StringBuilder,
+        TimestampNanosecondBuilder,
+    },
+    datatypes::{DataType, Field, Schema, TimeUnit},
+    ipc::writer::FileWriter,
+    record_batch::RecordBatch,
+};
+use log::{debug, info};
+use parquet::arrow::ArrowWriter;
+use tracing::instrument;
+use victory_wtf::Timepoint;
+
+use crate::{primitives::Primitives, topics::TopicKeyProvider};
+
+use super::{Datastore, DatastoreError};
+
+/// Name of the timestamp column in exported record batches
+pub const TIMESTAMP_COLUMN: &str = "timestamp";
+
+/// Arrow column builder for a single leaf topic, typed from its first value
+enum ColumnBuilder {
+    Integer(Int64Builder),
+    Float(Float64Builder),
+    Text(StringBuilder),
+    Boolean(BooleanBuilder),
+    Blob(BinaryBuilder),
+}
+
+impl ColumnBuilder {
+    fn for_primitive(value: &Primitives) -> Option<ColumnBuilder> {
+        match value {
+            Primitives::Integer(_) => Some(ColumnBuilder::Integer(Int64Builder::new())),
+            Primitives::Float(_) => Some(ColumnBuilder::Float(Float64Builder::new())),
+            Primitives::Text(_) => Some(ColumnBuilder::Text(StringBuilder::new())),
+            Primitives::Boolean(_) => Some(ColumnBuilder::Boolean(BooleanBuilder::new())),
+            Primitives::Blob(_) => Some(ColumnBuilder::Blob(BinaryBuilder::new())),
+            _ => None,
+        }
+    }
+
+    fn data_type(&self) -> DataType {
+        match self {
+            ColumnBuilder::Integer(_) => DataType::Int64,
+            ColumnBuilder::Float(_) => DataType::Float64,
+            ColumnBuilder::Text(_) => DataType::Utf8,
+            ColumnBuilder::Boolean(_) => DataType::Boolean,
+            ColumnBuilder::Blob(_) => DataType::Binary,
+        }
+    }
+
+    /// Append a value, writing null if there is none or it is `Unset`.
+    /// A value of another type is an error rather than a null, so no data is lost quietly.
+    fn append(&mut self, value: Option<&Primitives>) -> Result<(), String> {
+        match (&mut *self, value) {
+            (ColumnBuilder::Integer(b), Some(Primitives::Integer(v))) => b.append_value(*v),
+            (ColumnBuilder::Float(b), Some(Primitives::Float(v))) => b.append_value(*v),
+            (ColumnBuilder::Text(b), Some(Primitives::Text(v))) => b.append_value(v),
+            (ColumnBuilder::Boolean(b), Some(Primitives::Boolean(v))) => b.append_value(*v),
+            (ColumnBuilder::Blob(b), Some(Primitives::Blob(v))) => b.append_value(&v.data),
+            (ColumnBuilder::Integer(b), None | Some(Primitives::Unset)) => b.append_null(),
+            (ColumnBuilder::Float(b), None | Some(Primitives::Unset)) => b.append_null(),
+            (ColumnBuilder::Text(b), None | Some(Primitives::Unset)) => b.append_null(),
+            (ColumnBuilder::Boolean(b), None | Some(Primitives::Unset)) => b.append_null(),
+            (ColumnBuilder::Blob(b), None | Some(Primitives::Unset)) => b.append_null(),
+            (_, Some(other)) => {
+                return Err(format!(
+                    "expected {:?}, found {:?}",
+                    self.data_type(),
+                    other
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> ArrayRef {
+        match self {
+            ColumnBuilder::Integer(b) => Arc::new(b.finish()),
+            ColumnBuilder::Float(b) => Arc::new(b.finish()),
+            ColumnBuilder::Text(b) => Arc::new(b.finish()),
+            ColumnBuilder::Boolean(b) => Arc::new(b.finish()),
+            ColumnBuilder::Blob(b) => Arc::new(b.finish()),
+        }
+    }
+}
+
+/// Give every column that collides with an earlier one the first free `_1`, `_2`, ...
+/// suffix, never taking a name another column already has
+fn dedup_column_names(names: &mut [String]) {
+    let natural: HashSet<String> = names.iter().cloned().collect();
+    let mut used = HashSet::from([TIMESTAMP_COLUMN.to_string()]);
+    for name in names.iter_mut() {
+        if used.contains(name.as_str()) {
+            *name = (1..)
+                .map(|suffix| format!("{}_{}", name, suffix))
+                .find(|candidate| !used.contains(candidate) && !natural.contains(candidate))
+                .unwrap();
+        }
+        used.insert(name.clone());
+    }
+}
+
+impl Datastore {
+    /// Export every leaf topic under `topic` into a single record batch.
+    ///
+    /// The batch has a nanosecond `timestamp` column holding the union of all sample times,
+    /// plus one nullable column per leaf topic named relative to `topic`. Cells are null
+    /// where a topic has no sample at that time. Topics holding values other than
+    /// Integer, Float, Text, Boolean or Blob (such as `_type` markers) are skipped.
+    /// A leaf topic named `timestamp` gets the first free `_1`, `_2`, ... suffix instead.
+    /// A topic whose values change type, like Integer then Text, is an `Export` error.
+    #[instrument(skip_all)]
+    pub fn to_record_batch<T: TopicKeyProvider>(
+        &self,
+        topic: &T,
+    ) -> Result<RecordBatch, DatastoreError> {
+        let mut buckets = self.get_buckets_matching(topic)?;
+        buckets.sort_by_key(|b| b.read().unwrap().topic.display_name());
+
+        let mut times: BTreeSet<Timepoint> = BTreeSet::new();
+        let mut names = Vec::new();
+        let mut columns = Vec::new();
+        for bucket_handle in buckets.iter() {
+            let bucket = bucket_handle.read().unwrap();
+            let builder = bucket
+                .values
+                .values()
+                .find(|dp| dp.value != Primitives::Unset)
+                .and_then(|dp| ColumnBuilder::for_primitive(&dp.value));
+            let builder = match builder {
+                Some(builder) => builder,
+                None => {
+                    debug!(
+                        "[DB/export] Skipping {} with no exportable values",
+                        bucket.topic.display_name()
+                    );
+                    continue;
+                }
+            };
+
+            let name = match bucket.topic.key().remove_prefix(topic.key().clone()) {
+                Some(relative) if !relative.sections.is_empty() => relative.display_name(),
+                _ => bucket.topic.display_name(),
+            };
+            times.extend(bucket.values.keys().cloned());
+            names.push(name);
+            columns.push((builder, bucket_handle.clone()));
+        }
+        dedup_column_names(&mut names);
+
+        let mut fields = vec![Field::new(
+            TIMESTAMP_COLUMN,
+            DataType::Timestamp(TimeUnit::Nanosecond, None),
+            false,
+        )];
+        let mut arrays: Vec<ArrayRef> = Vec::with_capacity(columns.len() + 1);
+
+        let mut timestamps = TimestampNanosecondBuilder::with_capacity(times.len());
+        for time in times.iter() {
+            timestamps.append_value(time.ns() as i64);
+        }
+        arrays.push(Arc::new(timestamps.finish()));
+
+        for (name, (mut builder, bucket)) in names.into_iter().zip(columns) {
+            let bucket = bucket.read().unwrap();
+            for time in times.iter() {
+                builder
+                    .append(bucket.values.get(time).map(|dp| &dp.value))
+                    .map_err(|e| {
+                        DatastoreError::Export(format!(
+                            "Mixed value types in {} at {:.3}s: {}",
+                            bucket.topic.display_name(),
+                            time.secs(),
+                            e
+                        ))
+                    })?;
+            }
+            fields.push(Field::new(name, builder.data_type(), true));
+            arrays.push(builder.finish());
+        }
+
+        RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays)
+            .map_err(|e| DatastoreError::Export(format!("Error building record batch: {:?}", e)))
+    }
+
+    /// Export `topic` to a Parquet file, see `to_record_batch` for the layout
+    #[instrument(skip_all)]
+    pub fn write_parquet<T: TopicKeyProvider, P: AsRef<Path>>(
+        &self,
+        topic: &T,
+        path: P,
+    ) -> Result<(), DatastoreError> {
+        let batch = self.to_record_batch(topic)?;
+        let file = File::create(path.as_ref())?;
+        let mut writer = ArrowWriter::try_new(file, batch.schema(), None).map_err(|e| {
+            DatastoreError::Export(format!("Error creating parquet writer: {:?}", e))
+        })?;
+        writer
+            .write(&batch)
+            .map_err(|e| DatastoreError::Export(format!("Error writing parquet: {:?}", e)))?;
+        writer
+            .close()
+            .map_err(|e| DatastoreError::Export(format!("Error closing parquet: {:?}", e)))?;
+        info!(
+            "[DB/export] Wrote {} rows x {} columns to {:?}",
+            batch.num_rows(),
+            batch.num_columns(),
+            path.as_ref()
+        );
+        Ok(())
+    }
+
+    /// Export `topic` to an Arrow IPC file, see `to_record_batch` for the layout
+    #[instrument(skip_all)]
+    pub fn write_ipc<T: TopicKeyProvider, P: AsRef<Path>>(
+        &self,
+        topic: &T,
+        path: P,
+    ) -> Result<(), DatastoreError> {
+        let batch = self.to_record_batch(topic)?;
+        let file = File::create(path.as_ref())?;
+        let mut writer = FileWriter::try_new(file, &batch.schema())
+            .map_err(|e| DatastoreError::Export(format!("Error creating IPC writer: {:?}", e)))?;
+        writer
+            .write(&batch)
+            .map_err(|e| DatastoreError::Export(format!("Error writing IPC: {:?}", e)))?;
+        writer
+            .finish()
+            .map_err(|e| DatastoreError::Export(format!("Error closing IPC: {:?}", e)))?;
+        info!(
+            "[DB/export] Wrote {} rows x {} columns to {:?}",
+            batch.num_rows(),
+            batch.num_columns(),
+            path.as_ref()
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow::array::{Array, BooleanArray, Float64Array, Int64Array, StringArray};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use serde::Serialize;
+
+    use crate::topics::TopicKey;
+
+    use super::*;
+
+    #[derive(Serialize)]
+    struct Sample {
+        count: i32,
+        value: f64,
+        label: String,
+        armed: bool,
+    }
+
+    fn datastore_with_samples(topic: &TopicKey) -> Datastore {
+        let mut datastore = Datastore::new();
+        for i in 0..4 {
+            let sample = Sample {
+                count: i,
+                value: i as f64 * 0.5,
+                label: format!("sample_{}", i),
+                armed: i % 2 == 0,
+            };
+            datastore
+                .add_struct(topic, Timepoint::new_secs(i as f64), sample)
+                .unwrap();
+        }
+        // A sparse topic only written once, between samples
+        datastore
+            .add_primitive(
+                &topic.add_suffix(&TopicKey::from_str("sparse")),
+                Timepoint::new_secs(1.5),
+                Primitives::Integer(7),
+            )
+            .unwrap();
+        datastore
+    }
+
+    #[test]
+    fn test_to_record_batch() {
+        let topic = TopicKey::from_str("flight/sample");
+        let datastore = datastore_with_samples(&topic);
+
+        let batch = datastore.to_record_batch(&topic).unwrap();
+        let schema = batch.schema();
+        let names: Vec<&str> = schema.fields().iter().map(|f| f.name().as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                TIMESTAMP_COLUMN,
+                "armed",
+                "count",
+                "label",
+                "sparse",
+                "value"
+            ]
+        );
+        assert_eq!(batch.num_rows(), 5);
+
+        let count = batch
+            .column_by_name("count")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        assert_eq!(count.value(0), 0);
+        assert!(count.is_null(2), "No count sample at 1.5s");
+        assert_eq!(count.value(4), 3);
+
+        let value = batch.column_by_name("value").unwrap();
+        let value = value.as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!(value.value(4), 1.5);
+
+        let label = batch.column_by_name("label").unwrap();
+        let label = label.as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!(label.value(1), "sample_1");
+
+        let armed = batch.column_by_name("armed").unwrap();
+        let armed = armed.as_any().downcast_ref::<BooleanArray>().unwrap();
+        assert!(armed.value(0));
+
+        let sparse = batch.column_by_name("sparse").unwrap();
+        assert_eq!(sparse.null_count(), 4);
+    }
+
+    #[test]
+    fn test_to_record_batch_timestamp_topic() {
+        let topic = TopicKey::from_str("flight/gps");
+        let mut datastore = Datastore::new();
+        for (leaf, value) in [("timestamp", 100), ("timestamp_1", 200), ("alt", 300)] {
+            datastore
+                .add_primitive(
+                    &topic.add_suffix(&TopicKey::from_str(leaf)),
+                    Timepoint::new_secs(1.0),
+                    Primitives::Integer(value),
+                )
+                .unwrap();
+        }
+
+        let batch = datastore.to_record_batch(&topic).unwrap();
+        let schema = batch.schema();
+        let names: Vec<&str> = schema.fields().iter().map(|f| f.name().as_str()).collect();
+        assert_eq!(
+            names,
+            vec![TIMESTAMP_COLUMN, "alt", "timestamp_2", "timestamp_1"]
+        );
+
+        let gps_time = batch.column_by_name("timestamp_2").unwrap();
+        let gps_time = gps_time.as_any().downcast_ref::<Int64Array>().unwrap();
+        assert_eq!(gps_time.value(0), 100);
+    }
+
+    #[test]
+    fn test_to_record_batch_mixed_types() {
+        let topic = TopicKey::from_str("flight/sample");
+        let mut datastore = datastore_with_samples(&topic);
+        datastore
+            .add_primitive(
+                &topic.add_suffix(&TopicKey::from_str("count")),
+                Timepoint::new_secs(5.0),
+                Primitives::Text("five".to_string()),
+            )
+            .unwrap();
+
+        match datastore.to_record_batch(&topic) {
+            Err(DatastoreError::Export(msg)) => {
+                assert!(msg.contains("flight/sample/count"), "{}", msg)
+            }
+            other => panic!("Expected an export error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_write_parquet_and_ipc() {
+        let topic = TopicKey::from_str("flight/sample");
+        let datastore = datastore_with_samples(&topic);
+        let dir = std::env::temp_dir().join(format!("victory_export_{}", rand::random::<u32>()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let parquet_path = dir.join("sample.parquet");
+        datastore.write_parquet(&topic, &parquet_path).unwrap();
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&parquet_path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let rows: usize = reader.map(|b| b.unwrap().num_rows()).sum();
+        assert_eq!(rows, 5);
+
+        let ipc_path = dir.join("sample.arrow");
+        datastore.write_ipc(&topic, &ipc_path).unwrap();
+        let reader =
+            arrow::ipc::reader::FileReader::try_new(File::open(&ipc_path).unwrap(), None).unwrap();
+        let batches: Vec<RecordBatch> = reader.map(|b| b.unwrap()).collect();
+        assert_eq!(batches[0].num_columns(), 6);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
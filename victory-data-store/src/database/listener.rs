@@ -1,13 +1,41 @@
-use crate::{buckets::BucketHandle, datapoints::Datapoint, topics::TopicKey};
+use crate::{
+    buckets::BucketHandle,
+    datapoints::Datapoint,
+    topics::{TopicKey, TopicKeyHandle},
+};
 use std::{
     fmt::Debug,
-    sync::{Arc, Mutex},
+    sync::{Arc, Mutex, Weak},
 };
 
+pub type ListenerId = u64;
+pub type ListenerHandle = Arc<Mutex<dyn DataStoreListener>>;
+
+/// Where a batch of datapoints was written from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdateSource {
+    /// Written locally through `add_datapoints` and friends
+    Local,
+    /// Received from elsewhere through `add_datapoints_silent`, usually not to be forwarded again
+    Remote,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BucketEvent {
+    Created,
+    /// Retention evicted this many datapoints from the bucket
+    Evicted(usize),
+}
+
 pub trait DataStoreListener: Send {
-    fn on_datapoint(&mut self, datapoint: &Datapoint);
-    fn on_raw_datapoint(&mut self, _datapoint: &Datapoint) {}
-    fn on_bucket_update(&mut self, bucket: &BucketHandle);
+    /// Called once per insert with every new datapoint that matches the listener's filter
+    fn on_datapoints(&mut self, datapoints: &[Datapoint], source: UpdateSource);
+    /// Called with datapoints older than their bucket's latest datapoint, or replacing a value
+    /// at the same time. Defaults to `on_datapoints`.
+    fn on_late_datapoints(&mut self, datapoints: &[Datapoint], source: UpdateSource) {
+        self.on_datapoints(datapoints, source);
+    }
+    fn on_bucket_update(&mut self, _bucket: &BucketHandle, _event: BucketEvent) {}
     fn get_filter(&self) -> Option<TopicKey> {
         None
     }
@@ -18,17 +46,64 @@ impl Debug for dyn DataStoreListener {
     }
 }
 
+/// Keeps a listener registered with a `Datastore`.
+/// The datastore only holds a weak reference, so dropping the guard unregisters the listener.
+#[must_use = "dropping the guard immediately removes the listener"]
+pub struct ListenerGuard {
+    id: ListenerId,
+    listener: ListenerHandle,
+    removals: Weak<Mutex<Vec<ListenerId>>>,
+}
+
+impl ListenerGuard {
+    pub(crate) fn new(
+        id: ListenerId,
+        listener: ListenerHandle,
+        removals: Weak<Mutex<Vec<ListenerId>>>,
+    ) -> ListenerGuard {
+        ListenerGuard {
+            id,
+            listener,
+            removals,
+        }
+    }
+
+    pub fn id(&self) -> ListenerId {
+        self.id
+    }
+
+    pub fn listener(&self) -> &ListenerHandle {
+        &self.listener
+    }
+}
+
+impl Drop for ListenerGuard {
+    fn drop(&mut self) {
+        // The datastore prunes queued ids on its next notify
+        if let Some(removals) = self.removals.upgrade() {
+            removals.lock().unwrap().push(self.id);
+        }
+    }
+}
+
+impl Debug for ListenerGuard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ListenerGuard({})", self.id)
+    }
+}
+
 pub struct MockDataStoreListener {
     filter: TopicKey,
     pub updates: Vec<Datapoint>,
+    pub late_updates: Vec<Datapoint>,
+    /// Source of each `on_datapoints` call
+    pub batches: Vec<UpdateSource>,
+    pub bucket_events: Vec<(TopicKeyHandle, BucketEvent)>,
 }
 
 impl Default for MockDataStoreListener {
     fn default() -> Self {
-        MockDataStoreListener {
-            filter: TopicKey::empty(),
-            updates: Vec::new(),
-        }
+        MockDataStoreListener::new(TopicKey::empty())
     }
 }
 
@@ -37,6 +112,9 @@ impl MockDataStoreListener {
         MockDataStoreListener {
             filter,
             updates: Vec::new(),
+            late_updates: Vec::new(),
+            batches: Vec::new(),
+            bucket_events: Vec::new(),
         }
     }
 
@@ -52,22 +130,33 @@ impl Debug for MockDataStoreListener {
 }
 
 impl DataStoreListener for MockDataStoreListener {
-    fn on_datapoint(&mut self, datapoint: &Datapoint) {
-        self.updates.push(datapoint.clone());
+    fn on_datapoints(&mut self, datapoints: &[Datapoint], source: UpdateSource) {
+        self.updates.extend_from_slice(datapoints);
+        self.batches.push(source);
+    }
+
+    fn on_late_datapoints(&mut self, datapoints: &[Datapoint], _source: UpdateSource) {
+        self.late_updates.extend_from_slice(datapoints);
     }
 
     fn get_filter(&self) -> Option<TopicKey> {
         Some(self.filter.clone())
     }
 
-    fn on_bucket_update(&mut self, _bucket: &BucketHandle) {}
+    fn on_bucket_update(&mut self, bucket: &BucketHandle, event: BucketEvent) {
+        let topic = bucket.read().unwrap().topic.clone();
+        self.bucket_events.push((topic, event));
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use victory_wtf::Timepoint;
+    use victory_wtf::{Timepoint, Timespan};
 
-    use crate::{database::Datastore, topics::TopicKeyProvider};
+    use crate::{
+        database::{retention::RetentionPolicy, Datastore},
+        topics::TopicKeyProvider,
+    };
 
     use super::*;
 
@@ -82,7 +171,7 @@ mod tests {
         let listener = MockDataStoreListener::new(filter.clone());
         let listener = listener.as_handle();
 
-        datastore.add_listener(&filter, listener.clone()).unwrap();
+        let _guard = datastore.add_listener(&filter, listener.clone()).unwrap();
 
         // Write value to bucket a and b
         datastore.add_datapoints(vec![
@@ -98,9 +187,213 @@ mod tests {
             },
         ]);
 
+        let listener = listener.lock().unwrap();
+        assert_eq!(listener.updates.len(), 2);
+        assert_eq!(listener.updates[0].topic.key(), &topic_a);
+        assert_eq!(listener.updates[1].topic.key(), &topic_b);
+        // Both points arrive in a single batch
+        assert_eq!(listener.batches, vec![UpdateSource::Local]);
+    }
+
+    #[test]
+    pub fn test_datastore_listener_sources() {
+        let mut datastore = Datastore::new();
+        let topic = TopicKey::from_str("test/topic/a");
+        let filter = TopicKey::from_str("test");
+        let listener = MockDataStoreListener::new(filter.clone()).as_handle();
+        let _guard = datastore.add_listener(&filter, listener.clone()).unwrap();
+
+        datastore.add_datapoints(vec![Datapoint::new(
+            &topic,
+            Timepoint::new_secs(1.0),
+            1.into(),
+        )]);
+        datastore.add_datapoints_silent(vec![Datapoint::new(
+            &topic,
+            Timepoint::new_secs(2.0),
+            2.into(),
+        )]);
+        datastore
+            .add_datapoint(Datapoint::new(&topic, Timepoint::new_secs(3.0), 3.into()))
+            .unwrap();
+        // Nothing new, so no callback
+        datastore.add_datapoints(vec![Datapoint::new(
+            &topic,
+            Timepoint::new_secs(3.0),
+            3.into(),
+        )]);
+
+        let listener = listener.lock().unwrap();
+        assert_eq!(listener.updates.len(), 3);
+        assert_eq!(
+            listener.batches,
+            vec![
+                UpdateSource::Local,
+                UpdateSource::Remote,
+                UpdateSource::Local
+            ]
+        );
+    }
+
+    #[test]
+    pub fn test_datastore_listener_late_datapoints() {
+        let mut datastore = Datastore::new();
+        let topic = TopicKey::from_str("test/topic/a");
+        let filter = TopicKey::from_str("test");
+        let listener = MockDataStoreListener::new(filter.clone()).as_handle();
+        let _guard = datastore.add_listener(&filter, listener.clone()).unwrap();
+
+        datastore.add_datapoints_silent(vec![
+            Datapoint::new(&topic, Timepoint::new_secs(2.0), 2.into()),
+            Datapoint::new(&topic, Timepoint::new_secs(1.0), 1.into()),
+            Datapoint::new(&topic, Timepoint::new_secs(3.0), 3.into()),
+        ]);
+
+        let listener = listener.lock().unwrap();
+        let times = |datapoints: &[Datapoint]| -> Vec<f64> {
+            datapoints.iter().map(|d| d.time.secs()).collect()
+        };
+        assert_eq!(times(&listener.updates), vec![2.0, 3.0]);
+        assert_eq!(times(&listener.late_updates), vec![1.0]);
+    }
+
+    #[test]
+    pub fn test_datastore_listener_bucket_events() {
+        let mut datastore = Datastore::new();
+        let topic = TopicKey::from_str("test/topic/a");
+        let filter = TopicKey::from_str("test");
+        datastore.add_retention_rule(
+            &filter,
+            RetentionPolicy {
+                max_age: Some(Timespan::new_secs(5.0)),
+                max_rows: None,
+            },
+        );
+        let listener = MockDataStoreListener::new(filter.clone()).as_handle();
+        let _guard = datastore.add_listener(&filter, listener.clone()).unwrap();
+
+        datastore.create_bucket(&topic);
+        datastore.create_bucket(&TopicKey::from_str("other/topic"));
+        datastore.add_datapoints(vec![
+            Datapoint::new(&topic, Timepoint::new_secs(1.0), 1.into()),
+            Datapoint::new(&topic, Timepoint::new_secs(2.0), 2.into()),
+        ]);
+        datastore.enforce_retention(&Timepoint::new_secs(6.5));
+
+        let listener = listener.lock().unwrap();
+        assert_eq!(
+            listener.bucket_events,
+            vec![
+                (topic.handle(), BucketEvent::Created),
+                (topic.handle(), BucketEvent::Evicted(1))
+            ]
+        );
+    }
+
+    #[test]
+    pub fn test_datastore_listener_insert_evictions() {
+        let mut datastore = Datastore::new();
+        let topic = TopicKey::from_str("test/topic/a");
+        datastore.add_retention_rule(
+            &topic,
+            RetentionPolicy {
+                max_age: None,
+                max_rows: Some(2),
+            },
+        );
+        let listener = MockDataStoreListener::new(topic.clone()).as_handle();
+        let _guard = datastore.add_listener(&topic, listener.clone()).unwrap();
+
+        datastore.add_datapoints(
+            (0..4)
+                .map(|i| Datapoint::new(&topic, Timepoint::new_secs(i as f64), i.into()))
+                .collect(),
+        );
+        datastore.add_datapoints(vec![Datapoint::new(
+            &topic,
+            Timepoint::new_secs(4.0),
+            4.into(),
+        )]);
+
+        let listener = listener.lock().unwrap();
+        assert_eq!(listener.updates.len(), 5);
+        assert_eq!(
+            listener.bucket_events,
+            vec![
+                (topic.handle(), BucketEvent::Created),
+                (topic.handle(), BucketEvent::Evicted(2)),
+                (topic.handle(), BucketEvent::Evicted(1))
+            ]
+        );
+    }
+
+    #[test]
+    pub fn test_datastore_listener_glob() {
+        let mut datastore = Datastore::new();
+        let filter = TopicKey::from_str("robots/*/pose");
+        let listener = MockDataStoreListener::new(filter.clone()).as_handle();
+        let _guard = datastore.add_listener(&filter, listener.clone()).unwrap();
+
+        let topics = ["robots/a/pose/x", "robots/b/pose/y", "robots/a/twist/x"];
+        let datapoints = topics
+            .iter()
+            .map(|t| Datapoint::new(&TopicKey::from_str(t), Timepoint::zero(), 1.into()))
+            .collect();
+        datastore.add_datapoints(datapoints);
+
         let updates = listener.lock().unwrap().updates.clone();
-        assert_eq!(updates.len(), 2);
-        assert_eq!(updates[0].topic.key(), &topic_a);
-        assert_eq!(updates[1].topic.key(), &topic_b);
+        let mut names: Vec<String> = updates.iter().map(|u| u.topic.display_name()).collect();
+        names.sort();
+        assert_eq!(names, vec!["robots/a/pose/x", "robots/b/pose/y"]);
+    }
+
+    #[test]
+    pub fn test_datastore_remove_listener() {
+        let mut datastore = Datastore::new();
+        let topic = TopicKey::from_str("test/topic/a");
+        let filter = TopicKey::from_str("test/topic");
+        let write = |datastore: &mut Datastore, value: i32| {
+            datastore.add_datapoints(vec![Datapoint::new(
+                &topic,
+                Timepoint::new_secs(value as f64),
+                value.into(),
+            )]);
+        };
+
+        let explicit = MockDataStoreListener::new(filter.clone()).as_handle();
+        let explicit_guard = datastore.add_listener(&filter, explicit.clone()).unwrap();
+        let dropped = MockDataStoreListener::new(filter.clone()).as_handle();
+        let dropped_guard = datastore.add_listener(&filter, dropped.clone()).unwrap();
+        assert_eq!(datastore.get_listener_count(), 2);
+
+        write(&mut datastore, 1);
+        assert!(datastore.remove_listener(explicit_guard.id()));
+        assert!(!datastore.remove_listener(explicit_guard.id()));
+        drop(dropped_guard);
+        write(&mut datastore, 2);
+
+        assert_eq!(explicit.lock().unwrap().updates.len(), 1);
+        assert_eq!(dropped.lock().unwrap().updates.len(), 1);
+        assert_eq!(datastore.get_listener_count(), 0);
+    }
+
+    #[test]
+    pub fn test_datastore_listener_weak_cleanup() {
+        let mut datastore = Datastore::new();
+        let filter = TopicKey::from_str("test/topic");
+        let topic = TopicKey::from_str("test/topic/a");
+        let listener = MockDataStoreListener::new(filter.clone()).as_handle();
+        let handle: ListenerHandle = listener.clone();
+        datastore.add_listener_weak(&filter, &handle);
+        drop(handle);
+
+        datastore.add_datapoints(vec![Datapoint::new(&topic, Timepoint::zero(), 1.into())]);
+        assert_eq!(listener.lock().unwrap().updates.len(), 1);
+
+        // Once the last strong reference is gone the entry is pruned on the next notify
+        drop(listener);
+        assert_eq!(datastore.get_listener_count(), 1);
+        datastore.add_datapoints(vec![Datapoint::new(&topic, Timepoint::zero(), 2.into())]);
+        assert_eq!(datastore.get_listener_count(), 0);
     }
 }
@@ -1,37 +1,119 @@
 use crate::{
-    buckets::{Bucket, BucketHandle},
+    buckets::{
+        dedup::DedupPolicy,
+        reorder::{InsertOutcome, ReorderPolicy},
+        stats::BucketStats,
+        Bucket, BucketHandle,
+    },
     datapoints::Datapoint,
     primitives::{
         serde::{deserializer::PrimitiveDeserializer, serialize::to_map},
         Primitives,
     },
-    topics::{TopicKey, TopicKeyHandle, TopicKeyProvider},
+    topics::{
+        pattern::TopicPattern, tree::TopicTree, typed::TypedTopic, TopicKey, TopicKeyHandle,
+        TopicKeyProvider,
+    },
 };
-use listener::DataStoreListener;
-use log::{debug, trace, warn};
-use retention::RetentionPolicy;
+use listener::{BucketEvent, DataStoreListener, ListenerGuard, ListenerHandle, ListenerId, UpdateSource};
+use log::{debug, info, trace, warn};
+use retention::{RetentionPolicy, RetentionRules};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::{
     collections::{HashMap, HashSet},
-    sync::{Arc, Mutex},
+    sync::{Arc, Mutex, Weak},
 };
+use subscription::Subscription;
 use thiserror::Error;
 use tracing::{debug_span, info_span, instrument};
 use victory_wtf::Timepoint;
 use view::DataView;
+use wal::WalWriter;
 
 pub type DatastoreHandle = Arc<Mutex<Datastore>>;
 
+/// Insert into `bucket`, also returning how many datapoints retention evicted to make room
+fn insert_counting_evictions(
+    bucket: &BucketHandle,
+    datapoint: Datapoint,
+) -> (Result<InsertOutcome, String>, usize) {
+    let mut bucket = bucket.write().unwrap();
+    let evicted_before = bucket.get_evicted_count();
+    let outcome = bucket.insert(datapoint);
+    (outcome, bucket.get_evicted_count() - evicted_before)
+}
+
+pub mod downsample;
+pub mod export;
 pub mod listener;
+pub mod range;
 pub mod retention;
+pub mod sample;
+pub mod snapshot;
+pub mod subscription;
 pub mod view;
+pub mod wal;
 #[derive(Debug, Clone)]
 pub struct Datastore {
     buckets: HashMap<TopicKeyHandle, BucketHandle>,
-    listeners: HashMap<TopicKeyHandle, Vec<Arc<Mutex<dyn DataStoreListener>>>>,
-    pub retention: RetentionPolicy,
+    /// Index of all buckets by topic, used for subtree and pattern lookups
+    bucket_index: TopicTree<BucketHandle>,
+    /// Listeners with a plain topic filter, indexed by that topic
+    listeners: TopicTree<ListenerGroup>,
+    /// Listeners whose filter contains wildcards
+    pattern_listeners: HashMap<TopicKeyHandle, ListenerGroup>,
+    next_listener_id: ListenerId,
+    /// Ids queued for removal by dropped `ListenerGuard`s
+    listener_removals: Arc<Mutex<Vec<ListenerId>>>,
+    /// Channel subscriptions created by `subscribe`
+    subscriptions: Vec<Subscription>,
+    /// Rust type name of each topic registered with `register_topic`
+    typed_topics: HashMap<TopicKeyHandle, &'static str>,
+    pub retention: RetentionRules,
     /// Cache of topic searches and their resulting buckets
-    query_cache: HashMap<TopicKeyHandle, Vec<BucketHandle>>,
+    query_cache: HashMap<TopicKeyHandle, CachedQuery>,
+    query_cache_stats: QueryCacheStats,
+    /// Optional append-only log of every accepted datapoint
+    wal: Option<Arc<Mutex<WalWriter>>>,
+}
+
+/// Listeners sharing a topic filter, with the filter compiled once
+#[derive(Debug, Clone)]
+struct ListenerGroup {
+    pattern: TopicPattern,
+    listeners: Vec<(ListenerId, Weak<Mutex<dyn DataStoreListener>>)>,
+}
+
+impl ListenerGroup {
+    fn new(filter: &TopicKey) -> ListenerGroup {
+        ListenerGroup {
+            pattern: TopicPattern::new(filter),
+            listeners: Vec::new(),
+        }
+    }
+
+    /// Drop removed and dead listeners, returning true if any remain
+    fn prune(&mut self, removed: &HashSet<ListenerId>) -> bool {
+        self.listeners
+            .retain(|(id, listener)| !removed.contains(id) && listener.strong_count() > 0);
+        !self.listeners.is_empty()
+    }
+}
+
+/// A cached topic query and the buckets it matched
+#[derive(Debug, Clone)]
+struct CachedQuery {
+    pattern: TopicPattern,
+    buckets: Vec<BucketHandle>,
+}
+
+/// Counters for `get_buckets_matching_cached`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QueryCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Number of queries currently cached
+    pub entries: usize,
 }
 
 #[derive(Error, Debug)]
@@ -40,6 +122,44 @@ pub enum DatastoreError {
     Generic(String),
     #[error("Bucket not found for topic {0}")]
     BucketNotFound(TopicKey),
+    #[error("Snapshot Error: {0}")]
+    Snapshot(String),
+    #[error("Export Error: {0}")]
+    Export(String),
+    #[error("Write-ahead log Error: {0}")]
+    Wal(String),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("Type mismatch on {topic}: expected {expected}, found {found}")]
+    TypeMismatch {
+        topic: TopicKey,
+        expected: String,
+        found: String,
+    },
+}
+
+/// Check a stored `_type` marker against the struct type a reader expects
+pub(crate) fn check_struct_type(
+    topic: &TopicKey,
+    expected: Option<&str>,
+    stored: Option<&Primitives>,
+) -> Result<(), DatastoreError> {
+    let found = match stored {
+        Some(Primitives::StructType(found)) => Some(found.as_str()),
+        _ => None,
+    };
+    if found.is_none() || found == expected {
+        return Ok(());
+    }
+    Err(DatastoreError::TypeMismatch {
+        topic: topic.clone(),
+        expected: describe_struct_type(expected),
+        found: describe_struct_type(found),
+    })
+}
+
+fn describe_struct_type(struct_type: Option<&str>) -> String {
+    struct_type.unwrap_or("a non-struct value").to_string()
 }
 
 impl Default for Datastore {
@@ -52,10 +172,18 @@ impl Datastore {
     #[instrument(skip_all)]
     pub fn new() -> Datastore {
         Datastore {
-            listeners: HashMap::new(),
+            listeners: TopicTree::new(),
+            pattern_listeners: HashMap::new(),
+            next_listener_id: 0,
+            listener_removals: Arc::new(Mutex::new(Vec::new())),
+            subscriptions: Vec::new(),
+            typed_topics: HashMap::new(),
             buckets: HashMap::new(),
-            retention: RetentionPolicy::default(),
+            bucket_index: TopicTree::new(),
+            retention: RetentionRules::default(),
             query_cache: HashMap::new(),
+            query_cache_stats: QueryCacheStats::default(),
+            wal: None,
         }
     }
 
@@ -64,9 +192,65 @@ impl Datastore {
         Arc::new(Mutex::new(self))
     }
 
+    /// Set the default retention policy used by topics without a matching rule
     #[instrument]
     pub fn set_retention(&mut self, retention: RetentionPolicy) {
-        self.retention = retention;
+        self.retention.default = retention;
+    }
+
+    /// Add a retention rule for all topics under `prefix`.
+    /// Only affects buckets created afterwards until `apply_retention_rules` is called.
+    #[instrument(skip_all)]
+    pub fn add_retention_rule<T: TopicKeyProvider>(
+        &mut self,
+        prefix: &T,
+        retention: RetentionPolicy,
+    ) {
+        self.retention.add_rule(prefix, retention);
+    }
+
+    #[instrument(skip_all)]
+    pub fn remove_retention_rule<T: TopicKeyProvider>(
+        &mut self,
+        prefix: &T,
+    ) -> Option<RetentionPolicy> {
+        self.retention.remove_rule(prefix)
+    }
+
+    /// Re-resolve the retention policy of every existing bucket against the current rules
+    #[instrument(skip_all)]
+    pub fn apply_retention_rules(&mut self) {
+        for (topic, bucket) in self.buckets.iter() {
+            let policy = self.retention.resolve(topic.key()).clone();
+            bucket.write().unwrap().set_retention(policy);
+        }
+    }
+
+    /// Sweep every bucket and drop datapoints older than `now - max_age`.
+    /// Catches topics that have stopped updating and so never age out on insert.
+    /// Returns the number of evicted datapoints per topic, omitting topics with no evictions.
+    #[instrument(skip_all)]
+    pub fn enforce_retention(&mut self, now: &Timepoint) -> HashMap<TopicKeyHandle, usize> {
+        let mut evictions = HashMap::new();
+        let mut evicted_buckets = Vec::new();
+        for (topic, bucket) in self.buckets.iter() {
+            let evicted = bucket.write().unwrap().apply_retention(now);
+            if evicted > 0 {
+                evictions.insert(topic.clone(), evicted);
+                evicted_buckets.push((bucket.clone(), evicted));
+            }
+        }
+        for (bucket, evicted) in evicted_buckets {
+            self.notify_bucket_update(&bucket, BucketEvent::Evicted(evicted));
+        }
+        if !evictions.is_empty() {
+            info!(
+                "[DB/enforce_retention] Evicted {} datapoints across {} topics",
+                evictions.values().sum::<usize>(),
+                evictions.len()
+            );
+        }
+        evictions
     }
 
     #[instrument]
@@ -74,6 +258,29 @@ impl Datastore {
         self.query_cache.clear();
     }
 
+    pub fn get_query_cache_stats(&self) -> QueryCacheStats {
+        QueryCacheStats {
+            entries: self.query_cache.len(),
+            ..self.query_cache_stats
+        }
+    }
+
+    /// Add a new bucket to every cached query that matches its topic,
+    /// replacing `previous` if the bucket took over an existing topic
+    #[instrument(skip_all)]
+    fn update_query_cache(&mut self, bucket: &BucketHandle, previous: Option<&BucketHandle>) {
+        let topic = bucket.read().unwrap().topic.clone();
+        for cached in self.query_cache.values_mut() {
+            if !cached.pattern.matches(&topic) {
+                continue;
+            }
+            if let Some(previous) = previous {
+                cached.buckets.retain(|b| !Arc::ptr_eq(b, previous));
+            }
+            cached.buckets.push(bucket.clone());
+        }
+    }
+
     #[instrument(skip_all)]
     pub fn create_bucket<T: TopicKeyProvider>(&mut self, topic: &T) {
         if !self.buckets.contains_key(&topic.handle()) {
@@ -82,12 +289,47 @@ impl Datastore {
             bucket
                 .write()
                 .unwrap()
-                .set_retention(self.retention.clone());
-            self.buckets.insert(topic.handle().clone(), bucket);
-            self.clear_query_cache();
+                .set_retention(self.retention.resolve(topic.key()).clone());
+            self.buckets.insert(topic.handle().clone(), bucket.clone());
+            self.bucket_index.insert(topic, bucket.clone());
+            self.update_query_cache(&bucket, None);
+            self.notify_bucket_update(&bucket, BucketEvent::Created);
         }
     }
 
+    /// Statistics for every bucket matching `query`, sorted by topic display name
+    #[instrument(skip_all)]
+    pub fn stats<T: TopicKeyProvider>(
+        &self,
+        query: &T,
+    ) -> Result<Vec<BucketStats>, DatastoreError> {
+        let mut stats: Vec<BucketStats> = self
+            .get_buckets_matching(query)?
+            .iter()
+            .map(|b| b.read().unwrap().stats())
+            .collect();
+        stats.sort_by_key(|s| s.topic.display_name());
+        Ok(stats)
+    }
+
+    /// Set how late and colliding datapoints are handled for `topic`, creating its bucket if needed
+    #[instrument(skip_all)]
+    pub fn set_reorder_policy<T: TopicKeyProvider>(&mut self, topic: &T, reorder: ReorderPolicy) {
+        self.get_or_create_bucket(topic)
+            .write()
+            .unwrap()
+            .set_reorder(reorder);
+    }
+
+    /// Set how repeated values are handled for `topic`, creating its bucket if needed
+    #[instrument(skip_all)]
+    pub fn set_dedup_policy<T: TopicKeyProvider>(&mut self, topic: &T, dedup: DedupPolicy) {
+        self.get_or_create_bucket(topic)
+            .write()
+            .unwrap()
+            .set_dedup(dedup);
+    }
+
     #[instrument(skip_all)]
     pub fn get_or_create_bucket<T: TopicKeyProvider>(&mut self, topic: &T) -> BucketHandle {
         self.create_bucket(topic);
@@ -111,39 +353,130 @@ impl Datastore {
         parent_topic: &T,
     ) -> Result<Vec<BucketHandle>, DatastoreError> {
         // Check cache first
-        if let Some(cached_buckets) = self.query_cache.get(&parent_topic.handle()) {
-            return Ok(cached_buckets.clone());
+        if let Some(cached) = self.query_cache.get(&parent_topic.handle()) {
+            self.query_cache_stats.hits += 1;
+            return Ok(cached.buckets.clone());
         }
+        self.query_cache_stats.misses += 1;
 
         // If not in cache, get buckets and cache result
-        let buckets = self.get_buckets_matching(parent_topic)?;
-        self.query_cache
-            .insert(parent_topic.handle(), buckets.clone());
+        let pattern = TopicPattern::new(parent_topic);
+        let buckets = self.get_buckets_matching_pattern(&pattern)?;
+        self.query_cache.insert(
+            parent_topic.handle(),
+            CachedQuery {
+                pattern,
+                buckets: buckets.clone(),
+            },
+        );
         Ok(buckets)
     }
 
+    /// Get all buckets at or below `parent_topic`, which may contain `*` and `**` wildcards
     #[instrument(skip_all)]
     pub fn get_buckets_matching<T: TopicKeyProvider>(
         &self,
         parent_topic: &T,
+    ) -> Result<Vec<BucketHandle>, DatastoreError> {
+        self.get_buckets_matching_pattern(&TopicPattern::new(parent_topic))
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_buckets_matching_pattern(
+        &self,
+        pattern: &TopicPattern,
     ) -> Result<Vec<BucketHandle>, DatastoreError> {
         Ok(self
-            .buckets
-            .iter()
-            .filter_map(|(k, v)| {
-                if k.key().is_child_of(parent_topic.key()) {
-                    // trace!("Bucket {:?} matches topic {:?}", v, parent_topic.key());
-                    Some(v.clone())
-                } else if k.key() == parent_topic.key() {
-                    // trace!("Bucket {:?} matches topic {:?}", v, parent_topic.key());
-                    Some(v.clone())
-                } else {
-                    None
-                }
-            })
+            .bucket_index
+            .get_matching(pattern)
+            .into_iter()
+            .map(|(_, v)| v.clone())
             .collect::<Vec<BucketHandle>>())
     }
 
+    /// Add an existing bucket, replacing any bucket for the same topic
+    #[instrument(skip_all)]
+    pub(crate) fn insert_bucket(&mut self, bucket: BucketHandle) {
+        let topic = bucket.read().unwrap().topic.clone();
+        let previous = self.buckets.insert(topic.clone(), bucket.clone());
+        self.bucket_index.insert(&topic, bucket.clone());
+        self.update_query_cache(&bucket, previous.as_ref());
+        self.notify_bucket_update(&bucket, BucketEvent::Created);
+    }
+
+    /// Declare the type stored at `topic`. Afterwards `set` through a `TypedTopic` of any other
+    /// type fails instead of writing fields that the registered type can't read back.
+    #[instrument(skip_all)]
+    pub fn register_topic<S: Serialize + DeserializeOwned>(
+        &mut self,
+        topic: &TypedTopic<S>,
+    ) -> Result<(), DatastoreError> {
+        self.check_registered_type(topic)?;
+        self.check_stored_type(topic)?;
+        self.typed_topics.insert(topic.handle(), topic.type_name());
+        Ok(())
+    }
+
+    fn check_registered_type<S: Serialize + DeserializeOwned>(
+        &self,
+        topic: &TypedTopic<S>,
+    ) -> Result<(), DatastoreError> {
+        match self.typed_topics.get(topic.key()) {
+            Some(registered) if *registered != topic.type_name() => {
+                Err(DatastoreError::TypeMismatch {
+                    topic: topic.key().clone(),
+                    expected: registered.to_string(),
+                    found: topic.type_name().to_string(),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn check_stored_type<S: Serialize + DeserializeOwned>(
+        &self,
+        topic: &TypedTopic<S>,
+    ) -> Result<(), DatastoreError> {
+        let marker = topic.key().add_suffix(&TopicKey::from_str("_type"));
+        check_struct_type(
+            topic.key(),
+            topic.struct_type(),
+            self.get_latest_primitive(&marker).as_ref(),
+        )
+    }
+
+    /// Latest value of a typed topic, failing if the stored `_type` marker doesn't match `S`
+    #[instrument(skip_all)]
+    pub fn get<S: Serialize + DeserializeOwned>(
+        &self,
+        topic: &TypedTopic<S>,
+    ) -> Result<S, DatastoreError> {
+        self.check_stored_type(topic)?;
+        self.get_struct(topic)
+    }
+
+    /// Write a typed topic at the current time
+    #[instrument(skip_all)]
+    pub fn set<S: Serialize + DeserializeOwned>(
+        &mut self,
+        topic: &TypedTopic<S>,
+        value: S,
+    ) -> Result<(), DatastoreError> {
+        self.set_at(topic, Timepoint::now(), value)
+    }
+
+    /// Write a typed topic, failing if the topic was registered with another type
+    #[instrument(skip_all)]
+    pub fn set_at<S: Serialize + DeserializeOwned>(
+        &mut self,
+        topic: &TypedTopic<S>,
+        time: Timepoint,
+        value: S,
+    ) -> Result<(), DatastoreError> {
+        self.check_registered_type(topic)?;
+        self.add_struct(topic, time, value)
+    }
+
     #[instrument(skip_all)]
     pub fn get_struct<T, S>(&self, topic: &T) -> Result<S, DatastoreError>
     where
@@ -151,7 +484,8 @@ impl Datastore {
         S: DeserializeOwned,
     {
         // Get all the buckets that match the topic
-        let buckets = self.get_buckets_matching(topic)?;
+        let pattern = TopicPattern::new(topic);
+        let buckets = self.get_buckets_matching_pattern(&pattern)?;
 
         let mut value_map: HashMap<TopicKeyHandle, Primitives> = HashMap::new();
         for bucket in buckets {
@@ -163,11 +497,9 @@ impl Datastore {
                     value.topic.key(),
                     value.value
                 );
-                let key = value
-                    .topic
-                    .key()
-                    .remove_prefix(topic.key().clone())
-                    .unwrap();
+                let Some(key) = pattern.strip_match(&value.topic) else {
+                    continue;
+                };
 
                 value_map.insert(key.handle(), value.value.clone());
             }
@@ -228,42 +560,65 @@ impl Datastore {
         bucket.write().unwrap().add_primitive(time, value)
     }
 
-    /// Add datapoints without notifying listeners, usually used when receiving remote datapoints
-    /// that we want to store without triggering any local listeners
+    /// Add datapoints received from elsewhere, usually a remote peer.
+    /// Listeners are told the batch is `UpdateSource::Remote` so they don't forward it again.
     #[instrument(skip_all)]
     pub fn add_datapoints_silent(&mut self, datapoints: Vec<Datapoint>) {
-        let mut updated_datapoints = Vec::new();
+        self.insert_datapoints(datapoints, UpdateSource::Remote);
+    }
+
+    #[instrument(skip_all)]
+    pub fn add_datapoints(&mut self, datapoints: Vec<Datapoint>) {
+        self.insert_datapoints(datapoints, UpdateSource::Local);
+    }
+
+    /// Store datapoints, then log and notify listeners of the ones that were new
+    fn insert_datapoints(&mut self, datapoints: Vec<Datapoint>, source: UpdateSource) {
+        let mut inserted = Vec::new();
+        let mut evictions: Vec<(BucketHandle, usize)> = Vec::new();
         for datapoint in datapoints {
-            let inserted_dp = datapoint.clone();
-            if let Ok(n) =
-                self.add_primitive(&inserted_dp.topic, inserted_dp.time, inserted_dp.value)
-            {
-                if n > 0 {
-                    updated_datapoints.push(datapoint.clone());
+            let bucket = self.get_or_create_bucket(&datapoint.topic);
+            let (outcome, evicted) = insert_counting_evictions(&bucket, datapoint.clone());
+            if evicted > 0 {
+                match evictions.iter_mut().find(|(b, _)| Arc::ptr_eq(b, &bucket)) {
+                    Some((_, total)) => *total += evicted,
+                    None => evictions.push((bucket, evicted)),
                 }
             }
+            match outcome {
+                Ok(outcome) if outcome.is_stored() => inserted.push((datapoint, outcome)),
+                Ok(_) => {}
+                Err(e) => warn!("[DB/add_datapoints] {}", e),
+            }
         }
-        if !updated_datapoints.is_empty() {
-            self.notify_raw_datapoints(updated_datapoints);
+        self.publish_inserted(inserted, source);
+        for (bucket, evicted) in evictions {
+            self.notify_bucket_update(&bucket, BucketEvent::Evicted(evicted));
         }
     }
 
-    #[instrument(skip_all)]
-    pub fn add_datapoints(&mut self, datapoints: Vec<Datapoint>) {
-        let mut updated_datapoints = Vec::new();
-        for datapoint in datapoints.clone() {
-            let inserted_dp = datapoint.clone();
-            if let Ok(n) =
-                self.add_primitive(&inserted_dp.topic, inserted_dp.time, inserted_dp.value)
-            {
-                if n > 0 {
-                    updated_datapoints.push(datapoint.clone());
-                }
-            }
+    /// Log inserted datapoints and notify listeners, with late datapoints in their own batch
+    fn publish_inserted(
+        &mut self,
+        inserted: Vec<(Datapoint, InsertOutcome)>,
+        source: UpdateSource,
+    ) {
+        if inserted.is_empty() {
+            return;
         }
-        if !updated_datapoints.is_empty() {
-            self.notify_datapoints(updated_datapoints.clone());
-            self.notify_raw_datapoints(updated_datapoints);
+        let datapoints: Vec<Datapoint> = inserted.iter().map(|(d, _)| d.clone()).collect();
+        self.write_wal(&datapoints);
+
+        let (in_order, late): (Vec<_>, Vec<_>) = inserted
+            .into_iter()
+            .partition(|(_, outcome)| outcome.is_in_order());
+        if !in_order.is_empty() {
+            let in_order: Vec<Datapoint> = in_order.into_iter().map(|(d, _)| d).collect();
+            self.notify_datapoints(&in_order, source);
+        }
+        if !late.is_empty() {
+            let late: Vec<Datapoint> = late.into_iter().map(|(d, _)| d).collect();
+            self.notify_late_datapoints(&late, source);
         }
     }
 
@@ -320,6 +675,16 @@ impl Datastore {
         self.buckets.keys().cloned().collect()
     }
 
+    /// Keys of all buckets at or below `prefix`
+    #[instrument(skip_all)]
+    pub fn get_keys_under<T: TopicKeyProvider>(&self, prefix: &T) -> Vec<TopicKeyHandle> {
+        self.bucket_index
+            .get_subtree(prefix)
+            .into_iter()
+            .map(|(k, _)| k.clone())
+            .collect()
+    }
+
     #[instrument(skip_all)]
     pub fn get_all_display_names(&self) -> HashMap<TopicKeyHandle, String> {
         self.buckets
@@ -350,10 +715,15 @@ impl Datastore {
     }
 
     pub fn add_datapoint(&mut self, datapoint: Datapoint) -> Result<(), DatastoreError> {
-        self.create_bucket(&datapoint.topic);
-        let bucket = self.buckets.get_mut(&datapoint.topic).unwrap();
-        bucket.write().unwrap().add_datapoint(datapoint.clone()).unwrap();
-        self.notify_datapoints(vec![datapoint]);
+        let bucket = self.get_or_create_bucket(&datapoint.topic);
+        let (outcome, evicted) = insert_counting_evictions(&bucket, datapoint.clone());
+        let outcome = outcome.map_err(DatastoreError::Generic)?;
+        if outcome.is_stored() {
+            self.publish_inserted(vec![(datapoint, outcome)], UpdateSource::Local);
+        }
+        if evicted > 0 {
+            self.notify_bucket_update(&bucket, BucketEvent::Evicted(evicted));
+        }
         Ok(())
     }
 
@@ -389,17 +759,16 @@ impl Datastore {
         topic: &T,
         time: &Timepoint,
     ) -> Result<Option<S>, DatastoreError> {
-        let buckets = self.get_buckets_matching(topic)?;
+        let pattern = TopicPattern::new(topic);
+        let buckets = self.get_buckets_matching_pattern(&pattern)?;
         let mut value_map = HashMap::new();
 
         for bucket in buckets {
             let bucket = bucket.read().unwrap();
             if let Some(datapoint) = bucket.get_data_points_after(time).first() {
-                let key = datapoint
-                    .topic
-                    .key()
-                    .remove_prefix(topic.key().clone())
-                    .unwrap();
+                let Some(key) = pattern.strip_match(&datapoint.topic) else {
+                    continue;
+                };
                 value_map.insert(key.handle(), datapoint.value.clone());
             }
         }
@@ -413,64 +782,185 @@ impl Datastore {
             .map(Some)
             .map_err(|e| DatastoreError::Generic(format!("Error deserializing struct: {:?}", e)))
     }
+
+    /// Rebuild the struct as it was at `time`, each field from its last value at or before
+    /// `time`. `None` before the first write, see `range::struct_at`.
+    #[instrument(skip_all)]
+    pub fn get_struct_at<T: TopicKeyProvider, S: DeserializeOwned>(
+        &self,
+        topic: &T,
+        time: &Timepoint,
+    ) -> Result<Option<S>, DatastoreError> {
+        let pattern = TopicPattern::new(topic);
+        let buckets = self.get_buckets_matching_pattern(&pattern)?;
+        range::struct_at(&pattern, &buckets, time)
+    }
 }
 
 // ----------------------------
 // Listener Implementations
 // ----------------------------
 impl Datastore {
+    /// Register a listener for every topic overlapping `topic_query`.
+    /// The listener stays registered until the returned guard is dropped.
     #[instrument(skip(self, listener))]
     pub fn add_listener(
         &mut self,
         topic_query: &TopicKey,
-        listener: Arc<Mutex<dyn DataStoreListener>>,
-    ) -> Result<(), DatastoreError> {
+        listener: ListenerHandle,
+    ) -> Result<ListenerGuard, DatastoreError> {
+        let id = self.add_listener_weak(topic_query, &listener);
+        Ok(ListenerGuard::new(
+            id,
+            listener,
+            Arc::downgrade(&self.listener_removals),
+        ))
+    }
+
+    /// Register a listener without a guard. It is removed once the last
+    /// strong reference to it is dropped, or by `remove_listener`.
+    #[instrument(skip(self, listener))]
+    pub fn add_listener_weak(
+        &mut self,
+        topic_query: &TopicKey,
+        listener: &ListenerHandle,
+    ) -> ListenerId {
         debug!(
             "[DB/add_listener] Adding listener for topic: {:?}",
             topic_query
         );
+        let id = self.next_listener_id;
+        self.next_listener_id += 1;
+
+        let group = if TopicPattern::is_pattern(topic_query) {
+            self.pattern_listeners
+                .entry(topic_query.handle())
+                .or_insert_with(|| ListenerGroup::new(topic_query))
+        } else {
+            if self.listeners.get(topic_query).is_none() {
+                self.listeners
+                    .insert(topic_query, ListenerGroup::new(topic_query));
+            }
+            self.listeners.get_mut(topic_query).unwrap()
+        };
+        group.listeners.push((id, Arc::downgrade(listener)));
+        id
+    }
+
+    /// Unregister a listener, returning false if it wasn't registered
+    #[instrument(skip(self))]
+    pub fn remove_listener(&mut self, id: ListenerId) -> bool {
+        let before = self.get_listener_count();
+        self.prune_listeners(HashSet::from([id]));
+        self.get_listener_count() < before
+    }
+
+    /// Number of registered listeners, including dead ones not yet pruned
+    pub fn get_listener_count(&self) -> usize {
         self.listeners
-            .entry(topic_query.clone().handle())
-            .or_default()
-            .push(listener.clone());
-        Ok(())
+            .iter()
+            .into_iter()
+            .map(|(_, group)| group)
+            .chain(self.pattern_listeners.values())
+            .map(|group| group.listeners.len())
+            .sum()
     }
 
-    #[instrument(skip_all)]
-    pub fn notify_datapoints(&mut self, datapoints: Vec<Datapoint>) {
-        for (filter, listeners) in self.listeners.iter_mut() {
-            for datapoint in datapoints.iter() {
-                if datapoint.topic.key().matches(filter) {
-                    for listener in listeners.iter_mut() {
-                        listener.lock().unwrap().on_datapoint(datapoint);
-                        listener.lock().unwrap().on_raw_datapoint(datapoint);
-                    }
-                }
+    /// Drop listeners in `removed`, queued by dropped guards, or no longer alive
+    fn prune_listeners(&mut self, mut removed: HashSet<ListenerId>) {
+        removed.extend(self.listener_removals.lock().unwrap().drain(..));
+        self.listeners.retain(|_, group| group.prune(&removed));
+        self.pattern_listeners
+            .retain(|_, group| group.prune(&removed));
+    }
+
+    /// Live listeners whose filter is a parent or child of `topic`, or a pattern overlapping it.
+    /// Sets `stale` if a dead listener was skipped.
+    fn get_listeners_for(&self, topic: &TopicKey, stale: &mut bool) -> Vec<ListenerHandle> {
+        let literal = self
+            .listeners
+            .get_overlapping(topic)
+            .into_iter()
+            .map(|(_, group)| group);
+        let patterns = self
+            .pattern_listeners
+            .values()
+            .filter(|group| group.pattern.overlaps(topic));
+
+        let mut listeners = Vec::new();
+        for (_, listener) in literal.chain(patterns).flat_map(|g| g.listeners.iter()) {
+            match listener.upgrade() {
+                Some(listener) => listeners.push(listener),
+                None => *stale = true,
             }
         }
+        listeners
     }
 
-    #[instrument(skip_all)]
-    pub fn notify_raw_datapoints(&mut self, datapoints: Vec<Datapoint>) {
-        for (filter, listeners) in self.listeners.iter_mut() {
-            for datapoint in datapoints.iter() {
-                if datapoint.topic.key().matches(filter) {
-                    for listener in listeners.iter_mut() {
-                        listener.lock().unwrap().on_raw_datapoint(datapoint);
-                    }
-                }
+    /// Group `items` by the listeners interested in their topic, so each listener gets one batch.
+    /// Prunes before dispatch if guards were dropped, and after if dead listeners were found.
+    fn group_by_listener<T: Clone, F: Fn(&T) -> TopicKeyHandle>(
+        &mut self,
+        items: &[T],
+        topic: F,
+    ) -> Vec<(ListenerHandle, Vec<T>)> {
+        if !self.listener_removals.lock().unwrap().is_empty() {
+            self.prune_listeners(HashSet::new());
+        }
+        let mut stale = false;
+        let mut groups: Vec<(ListenerHandle, Vec<T>)> = Vec::new();
+        let mut index: HashMap<*const (), usize> = HashMap::new();
+        for item in items.iter() {
+            for listener in self.get_listeners_for(&topic(item), &mut stale) {
+                let ptr = Arc::as_ptr(&listener) as *const ();
+                let i = *index.entry(ptr).or_insert_with(|| {
+                    groups.push((listener, Vec::new()));
+                    groups.len() - 1
+                });
+                groups[i].1.push(item.clone());
             }
         }
+        if stale {
+            self.prune_listeners(HashSet::new());
+        }
+        groups
     }
 
+    /// Send each interested listener a single batch of `datapoints`
     #[instrument(skip_all)]
-    pub fn notify_bucket_updates(&mut self, _buckets: Vec<BucketHandle>) {}
+    pub fn notify_datapoints(&mut self, datapoints: &[Datapoint], source: UpdateSource) {
+        self.prune_subscriptions();
+        for (listener, datapoints) in self.group_by_listener(datapoints, |d| d.topic.clone()) {
+            listener.lock().unwrap().on_datapoints(&datapoints, source);
+        }
+    }
+
+    /// Like `notify_datapoints`, for datapoints that arrived out of order
+    #[instrument(skip_all)]
+    pub fn notify_late_datapoints(&mut self, datapoints: &[Datapoint], source: UpdateSource) {
+        self.prune_subscriptions();
+        for (listener, datapoints) in self.group_by_listener(datapoints, |d| d.topic.clone()) {
+            listener
+                .lock()
+                .unwrap()
+                .on_late_datapoints(&datapoints, source);
+        }
+    }
+
+    #[instrument(skip_all)]
+    pub fn notify_bucket_update(&mut self, bucket: &BucketHandle, event: BucketEvent) {
+        let buckets = std::slice::from_ref(bucket);
+        for (listener, _) in self.group_by_listener(buckets, |b| b.read().unwrap().topic.clone()) {
+            listener.lock().unwrap().on_bucket_update(bucket, event);
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
 
     use crate::database::*;
+    use victory_wtf::Timespan;
 
     #[test]
     pub fn test_datastore_creation() {
@@ -547,6 +1037,86 @@ mod tests {
         assert!(keys.contains(&topic_parent));
     }
 
+    #[test]
+    pub fn test_datastore_get_buckets_matching_glob() {
+        let mut datastore = Datastore::new();
+        for topic in [
+            "robots/a/pose/x",
+            "robots/b/pose/x",
+            "robots/b/pose/y",
+            "robots/b/twist/x",
+            "sensors/temperature",
+            "sensors/engine/left/temperature",
+            "sensors/engine/left/pressure",
+        ] {
+            datastore.create_bucket(&TopicKey::from_str(topic));
+        }
+
+        let count = |datastore: &mut Datastore, query: &str| {
+            let query = TopicKey::from_str(query);
+            let uncached = datastore.get_buckets_matching(&query).unwrap().len();
+            let cached = datastore.get_buckets_matching_cached(&query).unwrap().len();
+            assert_eq!(uncached, cached);
+            cached
+        };
+        assert_eq!(count(&mut datastore, "robots/*/pose/x"), 2);
+        assert_eq!(count(&mut datastore, "robots/*/pose"), 3);
+        assert_eq!(count(&mut datastore, "sensors/**/temperature"), 2);
+        assert_eq!(count(&mut datastore, "**/x"), 3);
+
+        // A new matching bucket invalidates the cached glob query
+        datastore.create_bucket(&TopicKey::from_str("robots/c/pose/x"));
+        assert_eq!(count(&mut datastore, "robots/*/pose/x"), 3);
+    }
+
+    #[test]
+    pub fn test_datastore_get_keys_under() {
+        let mut datastore = Datastore::new();
+        for topic in ["robots/a/pose/x", "robots/a/pose/y", "robots/b/pose/x"] {
+            datastore.create_bucket(&TopicKey::from_str(topic));
+        }
+
+        let mut keys: Vec<String> = datastore
+            .get_keys_under(&TopicKey::from_str("robots/a"))
+            .iter()
+            .map(|k| k.display_name())
+            .collect();
+        keys.sort();
+        assert_eq!(keys, vec!["robots/a/pose/x", "robots/a/pose/y"]);
+        assert_eq!(datastore.get_keys_under(&TopicKey::empty()).len(), 3);
+        assert!(datastore
+            .get_keys_under(&TopicKey::from_str("robots/c"))
+            .is_empty());
+    }
+
+    #[test]
+    pub fn test_datastore_query_cache_incremental() {
+        let mut datastore = Datastore::new();
+        let query_a = TopicKey::from_str("robots/a");
+        let query_b = TopicKey::from_str("robots/b");
+        datastore.create_bucket(&TopicKey::from_str("robots/a/pose/x"));
+        datastore.create_bucket(&TopicKey::from_str("robots/b/pose/x"));
+
+        assert_eq!(datastore.get_buckets_matching_cached(&query_a).unwrap().len(), 1);
+        assert_eq!(datastore.get_buckets_matching_cached(&query_b).unwrap().len(), 1);
+        let stats = datastore.get_query_cache_stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (0, 2, 2));
+
+        // A new field under robots/a only extends that cached query
+        datastore.create_bucket(&TopicKey::from_str("robots/a/pose/y"));
+        assert_eq!(datastore.get_buckets_matching_cached(&query_a).unwrap().len(), 2);
+        assert_eq!(datastore.get_buckets_matching_cached(&query_b).unwrap().len(), 1);
+        let stats = datastore.get_query_cache_stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (2, 2, 2));
+
+        // Replacing an existing bucket swaps it in the cache rather than duplicating it
+        let replacement = Bucket::new(&TopicKey::from_str("robots/a/pose/y"));
+        datastore.insert_bucket(replacement.clone());
+        let cached = datastore.get_buckets_matching_cached(&query_a).unwrap();
+        assert_eq!(cached.len(), 2);
+        assert!(cached.iter().any(|b| Arc::ptr_eq(b, &replacement)));
+    }
+
     #[test]
     pub fn test_datastore_add_primitive() {
         let mut datastore = Datastore::new();
@@ -588,6 +1158,40 @@ mod tests {
         let result: TestStruct = datastore.get_struct(&topic).unwrap();
         assert_eq!(result, test_struct);
     }
+
+    #[test]
+    pub fn test_datastore_get_struct_glob() {
+        let mut datastore = Datastore::new();
+        for i in 0..3 {
+            let value = TestStructA {
+                a: i,
+                b: format!("test_{}", i),
+            };
+            datastore
+                .add_struct(
+                    &TopicKey::from_str("robots/a/state"),
+                    Timepoint::new_secs(i as f64),
+                    value,
+                )
+                .unwrap();
+        }
+        datastore
+            .add_primitive(
+                &TopicKey::from_str("robots/b/twist"),
+                Timepoint::new_secs(1.0),
+                1.into(),
+            )
+            .unwrap();
+
+        let glob = TopicKey::from_str("robots/*/state");
+        let result: TestStructA = datastore.get_struct(&glob).unwrap();
+        assert_eq!(result.a, 2);
+
+        let result: Option<TestStructA> = datastore
+            .get_struct_after(&glob, &Timepoint::new_secs(0.5))
+            .unwrap();
+        assert_eq!(result.unwrap().b, "test_1");
+    }
     #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
     struct TestStructA {
         a: i32,
@@ -684,4 +1288,196 @@ mod tests {
         let result: Option<TestStructA> = datastore.get_struct_after(&topic, &time_after).unwrap();
         assert!(result.is_none());
     }
+
+    #[test]
+    pub fn test_datastore_get_struct_at() {
+        let mut datastore = Datastore::new();
+        let topic: TopicKey = "/test/topic".into();
+        for i in 1..4 {
+            let value = TestStructA {
+                a: i,
+                b: "test".to_string(),
+            };
+            datastore
+                .add_struct(&topic, Timepoint::new_secs(i as f64), value)
+                .unwrap();
+        }
+
+        let result: TestStructA = datastore
+            .get_struct_at(&topic, &Timepoint::new_secs(2.5))
+            .unwrap()
+            .unwrap();
+        assert_eq!(result.a, 2);
+        // b was only written at 1s but is still part of the struct
+        assert_eq!(result.b, "test");
+
+        // A write at exactly `time` is included
+        let result: TestStructA = datastore
+            .get_struct_at(&topic, &Timepoint::new_secs(2.0))
+            .unwrap()
+            .unwrap();
+        assert_eq!(result.a, 2);
+
+        let result: Option<TestStructA> = datastore
+            .get_struct_at(&topic, &Timepoint::new_secs(0.5))
+            .unwrap();
+        assert!(result.is_none(), "Nothing was written before 1s");
+
+        let result: Option<TestStructA> = datastore
+            .get_struct_at(&TopicKey::from_str("/missing"), &Timepoint::new_secs(2.5))
+            .unwrap();
+        assert!(result.is_none());
+    }
+
+    #[test]
+    pub fn test_datastore_dedup_policy() {
+        let mut datastore = Datastore::new();
+        let heartbeat: TopicKey = "robot/heartbeat".into();
+        let status: TopicKey = "robot/status".into();
+        datastore.set_dedup_policy(&heartbeat, DedupPolicy::KeepAll);
+
+        for i in 0..3 {
+            let time = Timepoint::new_secs(i as f64);
+            datastore.add_datapoints(vec![
+                Datapoint::new(&heartbeat, time.clone(), true.into()),
+                Datapoint::new(&status, time, true.into()),
+            ]);
+        }
+
+        let heartbeat = datastore.get_bucket(&heartbeat).unwrap();
+        assert_eq!(heartbeat.read().unwrap().values.len(), 3);
+        let status = datastore.get_bucket(&status).unwrap();
+        assert_eq!(status.read().unwrap().values.len(), 1);
+        assert_eq!(status.read().unwrap().get_rejected_count(), 2);
+    }
+
+    #[test]
+    pub fn test_datastore_typed_topics() {
+        let mut datastore = Datastore::new();
+        let topic: TypedTopic<TestStructA> = "robot/a".into();
+        let value = TestStructA {
+            a: 1,
+            b: "two".to_string(),
+        };
+
+        datastore.register_topic(&topic).unwrap();
+        datastore
+            .set_at(&topic, Timepoint::new_secs(1.0), value.clone())
+            .unwrap();
+        assert_eq!(datastore.get(&topic).unwrap(), value);
+
+        // Same topic, wrong type
+        let wrong: TypedTopic<TestStructB> = "robot/a".into();
+        assert!(matches!(
+            datastore.get(&wrong),
+            Err(DatastoreError::TypeMismatch { .. })
+        ));
+        assert!(datastore.register_topic(&wrong).is_err());
+        let wrong_value = TestStructB {
+            c: 1,
+            d: "two".to_string(),
+        };
+        assert!(datastore.set(&wrong, wrong_value).is_err());
+
+        let count: TypedTopic<u64> = "robot/count".into();
+        datastore.register_topic(&count).unwrap();
+        datastore.set(&count, 42).unwrap();
+        assert_eq!(datastore.get(&count).unwrap(), 42);
+
+        // Primitives have no `_type` marker, the registered Rust type still tells them apart
+        let wrong: TypedTopic<String> = "robot/count".into();
+        assert!(matches!(
+            datastore.set(&wrong, "42".to_string()),
+            Err(DatastoreError::TypeMismatch { .. })
+        ));
+        assert!(datastore.register_topic(&wrong).is_err());
+        assert_eq!(datastore.get(&count).unwrap(), 42);
+    }
+
+    #[test]
+    pub fn test_datastore_stats() {
+        let mut datastore = Datastore::new();
+        for i in 0..4 {
+            let time = Timepoint::new_secs(i as f64 * 0.5);
+            datastore.add_datapoints(vec![
+                Datapoint::new(&TopicKey::from_str("robot/b"), time.clone(), i.into()),
+                Datapoint::new(&TopicKey::from_str("robot/a"), time, 0.into()),
+            ]);
+        }
+
+        let stats = datastore.stats(&TopicKey::from_str("robot")).unwrap();
+        let names: Vec<String> = stats.iter().map(|s| s.topic.display_name()).collect();
+        assert_eq!(names, vec!["robot/a", "robot/b"]);
+        assert_eq!((stats[0].rows, stats[0].rejected), (1, 3));
+        assert_eq!(stats[1].rows, 4);
+        assert!((stats[1].rate_hz.unwrap() - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    pub fn test_datastore_enforce_retention() {
+        let mut datastore = Datastore::new();
+        datastore.set_retention(RetentionPolicy {
+            max_age: Some(Timespan::new_secs(5.0)),
+            max_rows: None,
+        });
+        let topic_stale: TopicKey = "test/stale".into();
+        let topic_live: TopicKey = "test/live".into();
+
+        for i in 0..5 {
+            let time = Timepoint::new_secs(i as f64);
+            datastore.add_primitive(&topic_stale, time.clone(), i.into()).unwrap();
+            datastore.add_primitive(&topic_live, time, i.into()).unwrap();
+        }
+        datastore
+            .add_primitive(&topic_live, Timepoint::new_secs(20.0), 20.into())
+            .unwrap();
+
+        // The live topic aged out its old values on insert
+        assert_eq!(datastore.get_datapoints(&topic_live).unwrap().len(), 1);
+        // The stale topic keeps everything until a sweep
+        assert_eq!(datastore.get_datapoints(&topic_stale).unwrap().len(), 5);
+
+        let evictions = datastore.enforce_retention(&Timepoint::new_secs(7.5));
+        assert_eq!(evictions.len(), 1);
+        assert_eq!(evictions[&topic_stale.handle()], 3);
+        assert_eq!(datastore.get_datapoints(&topic_stale).unwrap().len(), 2);
+
+        let bucket = datastore.get_bucket(&topic_stale).unwrap();
+        assert_eq!(bucket.read().unwrap().get_evicted_count(), 3);
+    }
+
+    #[test]
+    pub fn test_datastore_retention_rules() {
+        let mut datastore = Datastore::new();
+        datastore.set_retention(RetentionPolicy {
+            max_age: None,
+            max_rows: Some(10),
+        });
+        datastore.add_retention_rule(
+            &TopicKey::from_str("sensors/imu"),
+            RetentionPolicy {
+                max_age: None,
+                max_rows: Some(1000),
+            },
+        );
+
+        let topic_imu: TopicKey = "sensors/imu/accel/x".into();
+        let topic_config: TopicKey = "config/gains/kp".into();
+        datastore.create_bucket(&topic_imu);
+        datastore.create_bucket(&topic_config);
+
+        let max_rows = |datastore: &Datastore, topic: &TopicKey| {
+            let bucket = datastore.get_bucket(topic).unwrap();
+            let max_rows = bucket.read().unwrap().get_retention().max_rows;
+            max_rows
+        };
+        assert_eq!(max_rows(&datastore, &topic_imu), Some(1000));
+        assert_eq!(max_rows(&datastore, &topic_config), Some(10));
+
+        // New rules only reach existing buckets once re-applied
+        datastore.add_retention_rule(&TopicKey::from_str("config"), RetentionPolicy::default());
+        assert_eq!(max_rows(&datastore, &topic_config), Some(10));
+        datastore.apply_retention_rules();
+        assert_eq!(max_rows(&datastore, &topic_config), None);
+    }
 }
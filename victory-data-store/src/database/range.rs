@@ -0,0 +1,339 @@
+use std::{
+    collections::{BTreeSet, HashMap, VecDeque},
+    marker::PhantomData,
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tracing::instrument;
+use victory_wtf::Timepoint;
+
+use crate::{
+    buckets::BucketHandle,
+    datapoints::Datapoint,
+    primitives::{serde::deserializer::PrimitiveDeserializer, Primitives},
+    topics::{pattern::TopicPattern, TopicKeyHandle, TopicKeyProvider},
+};
+
+use super::{Datastore, DatastoreError};
+
+/// Half-open time window `[start, end)` with an optional result limit and ordering
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimeRange {
+    pub start: Timepoint,
+    pub end: Timepoint,
+    /// Maximum number of results, counted from `end` when reversed
+    pub limit: Option<usize>,
+    /// Return newest results first
+    pub reverse: bool,
+}
+
+impl TimeRange {
+    pub fn new(start: Timepoint, end: Timepoint) -> TimeRange {
+        TimeRange {
+            start,
+            end,
+            limit: None,
+            reverse: false,
+        }
+    }
+
+    pub fn with_limit(mut self, limit: usize) -> TimeRange {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn reversed(mut self) -> TimeRange {
+        self.reverse = true;
+        self
+    }
+
+    pub fn contains(&self, time: &Timepoint) -> bool {
+        *time >= self.start && *time < self.end
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start >= self.end
+    }
+
+    /// Sort datapoints by time in the range's order and apply its limit
+    pub(crate) fn order(&self, datapoints: &mut Vec<Datapoint>) {
+        if self.reverse {
+            datapoints.sort_by(|a, b| b.time.cmp(&a.time));
+        } else {
+            datapoints.sort_by(|a, b| a.time.cmp(&b.time));
+        }
+        if let Some(limit) = self.limit {
+            datapoints.truncate(limit);
+        }
+    }
+}
+
+/// Deserialize a struct from the values of `buckets` as of `time` (inclusive).
+/// Fields with no value at or before `time` are left out, as are buckets outside `pattern`.
+pub(crate) fn struct_at<S: DeserializeOwned>(
+    pattern: &TopicPattern,
+    buckets: &[BucketHandle],
+    time: &Timepoint,
+) -> Result<Option<S>, DatastoreError> {
+    let mut value_map: HashMap<TopicKeyHandle, Primitives> = HashMap::new();
+    for bucket in buckets {
+        let bucket = bucket.read().unwrap();
+        if let Some((_, datapoint)) = bucket.values.range(..=time.clone()).next_back() {
+            let Some(key) = pattern.strip_match(&datapoint.topic) else {
+                continue;
+            };
+            value_map.insert(key.handle(), datapoint.value.clone());
+        }
+    }
+
+    if value_map.is_empty() {
+        return Ok(None);
+    }
+
+    let mut deserializer = PrimitiveDeserializer::new(&value_map);
+    S::deserialize(&mut deserializer)
+        .map(Some)
+        .map_err(|e| DatastoreError::Generic(format!("Error deserializing struct: {:?}", e)))
+}
+
+impl Datastore {
+    /// Datapoints of every topic under `topic` within `range`, ordered by time
+    #[instrument(skip_all)]
+    pub fn get_datapoints_range<T: TopicKeyProvider>(
+        &self,
+        topic: &T,
+        range: &TimeRange,
+    ) -> Result<Vec<Datapoint>, DatastoreError> {
+        let buckets = self.get_buckets_matching(topic)?;
+        let mut datapoints = Vec::new();
+        for bucket in buckets {
+            let bucket = bucket.read().unwrap();
+            datapoints.extend(bucket.get_data_points_range(range).into_iter().cloned());
+        }
+        range.order(&mut datapoints);
+        Ok(datapoints)
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_primitives_range<T: TopicKeyProvider>(
+        &self,
+        topic: &T,
+        range: &TimeRange,
+    ) -> Result<Vec<Primitives>, DatastoreError> {
+        let datapoints = self.get_datapoints_range(topic, range)?;
+        Ok(datapoints.into_iter().map(|dp| dp.value).collect())
+    }
+
+    /// One struct per distinct write time within `range`, each built from the
+    /// latest value of every field at that time
+    #[instrument(skip_all)]
+    pub fn get_structs_range<T: TopicKeyProvider, S: DeserializeOwned>(
+        &self,
+        topic: &T,
+        range: &TimeRange,
+    ) -> Result<Vec<(Timepoint, S)>, DatastoreError> {
+        self.iter_structs(topic, range)?.collect()
+    }
+
+    /// Lazily rebuild the struct under `topic` each time any of its fields changes within `range`.
+    /// Structs are only deserialized as the iterator is advanced.
+    #[instrument(skip_all)]
+    pub fn iter_structs<T: TopicKeyProvider, S: DeserializeOwned>(
+        &self,
+        topic: &T,
+        range: &TimeRange,
+    ) -> Result<StructIter<S>, DatastoreError> {
+        let pattern = TopicPattern::new(topic);
+        let buckets = self.get_buckets_matching_pattern(&pattern)?;
+        let mut times = BTreeSet::new();
+        for bucket in buckets.iter() {
+            let bucket = bucket.read().unwrap();
+            times.extend(
+                bucket
+                    .get_data_points_range(range)
+                    .into_iter()
+                    .map(|dp| dp.time.clone()),
+            );
+        }
+
+        let limit = range.limit.unwrap_or(usize::MAX);
+        let times: VecDeque<Timepoint> = if range.reverse {
+            times.into_iter().rev().take(limit).collect()
+        } else {
+            times.into_iter().take(limit).collect()
+        };
+
+        Ok(StructIter {
+            pattern,
+            buckets,
+            times,
+            _marker: PhantomData,
+        })
+    }
+}
+
+/// Iterator over `(time, struct)` pairs, see `Datastore::iter_structs`
+pub struct StructIter<S> {
+    pattern: TopicPattern,
+    buckets: Vec<BucketHandle>,
+    times: VecDeque<Timepoint>,
+    _marker: PhantomData<S>,
+}
+
+impl<S: DeserializeOwned> Iterator for StructIter<S> {
+    type Item = Result<(Timepoint, S), DatastoreError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(time) = self.times.pop_front() {
+            match struct_at(&self.pattern, &self.buckets, &time) {
+                Ok(Some(value)) => return Some(Ok((time, value))),
+                Ok(None) => continue,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.times.len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::topics::TopicKey;
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+    struct Pose {
+        x: i32,
+        y: i32,
+    }
+
+    #[test]
+    fn test_datastore_get_range() {
+        let mut datastore = Datastore::new();
+        let topic: TopicKey = "robot/pose".into();
+        for i in 0..10 {
+            let pose = Pose { x: i, y: i / 4 };
+            datastore
+                .add_struct(&topic, Timepoint::new_secs(i as f64), pose)
+                .unwrap();
+        }
+        let x: TopicKey = "robot/pose/x".into();
+
+        let range = TimeRange::new(Timepoint::new_secs(2.0), Timepoint::new_secs(5.0));
+        let values = datastore.get_primitives_range(&x, &range).unwrap();
+        assert_eq!(values, vec![2.into(), 3.into(), 4.into()]);
+
+        let range = range.with_limit(2).reversed();
+        let values = datastore.get_primitives_range(&x, &range).unwrap();
+        assert_eq!(values, vec![4.into(), 3.into()]);
+
+        // Both fields of the struct are interleaved by time
+        let range = TimeRange::new(Timepoint::new_secs(3.0), Timepoint::new_secs(5.0));
+        let datapoints = datastore.get_datapoints_range(&topic, &range).unwrap();
+        assert_eq!(datapoints.len(), 3);
+        assert!(datapoints.windows(2).all(|w| w[0].time <= w[1].time));
+
+        let empty = TimeRange::new(Timepoint::new_secs(5.0), Timepoint::new_secs(5.0));
+        assert!(datastore
+            .get_datapoints_range(&topic, &empty)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_datastore_get_structs_range() {
+        let mut datastore = Datastore::new();
+        let topic: TopicKey = "robot/pose".into();
+        for i in 0..10 {
+            let pose = Pose { x: i, y: i / 4 };
+            datastore
+                .add_struct(&topic, Timepoint::new_secs(i as f64), pose)
+                .unwrap();
+        }
+
+        let range = TimeRange::new(Timepoint::new_secs(3.0), Timepoint::new_secs(6.0));
+        let structs: Vec<(Timepoint, Pose)> = datastore.get_structs_range(&topic, &range).unwrap();
+        // y only changes at 4s, but is held for the other rows
+        assert_eq!(
+            structs,
+            vec![
+                (Timepoint::new_secs(3.0), Pose { x: 3, y: 0 }),
+                (Timepoint::new_secs(4.0), Pose { x: 4, y: 1 }),
+                (Timepoint::new_secs(5.0), Pose { x: 5, y: 1 }),
+            ]
+        );
+
+        let range = range.with_limit(1).reversed();
+        let structs: Vec<(Timepoint, Pose)> = datastore.get_structs_range(&topic, &range).unwrap();
+        assert_eq!(
+            structs,
+            vec![(Timepoint::new_secs(5.0), Pose { x: 5, y: 1 })]
+        );
+    }
+
+    #[test]
+    fn test_datastore_iter_structs() {
+        let mut datastore = Datastore::new();
+        let topic: TopicKey = "robot/pose".into();
+        datastore
+            .add_struct(&topic, Timepoint::new_secs(0.0), Pose { x: 0, y: 0 })
+            .unwrap();
+        // Only y changes
+        datastore
+            .add_struct(&topic, Timepoint::new_secs(1.0), Pose { x: 0, y: 1 })
+            .unwrap();
+        // Nothing changes, so no struct is yielded
+        datastore
+            .add_struct(&topic, Timepoint::new_secs(2.0), Pose { x: 0, y: 1 })
+            .unwrap();
+        datastore
+            .add_struct(&topic, Timepoint::new_secs(3.0), Pose { x: 5, y: 1 })
+            .unwrap();
+
+        let range = TimeRange::new(Timepoint::zero(), Timepoint::new_secs(10.0));
+        let mut iter = datastore.iter_structs::<_, Pose>(&topic, &range).unwrap();
+        assert_eq!(iter.size_hint(), (0, Some(3)));
+        let (time, pose) = iter.next().unwrap().unwrap();
+        assert_eq!(time, Timepoint::zero());
+        assert_eq!(pose, Pose { x: 0, y: 0 });
+
+        let rest: Vec<Pose> = iter.map(|r| r.unwrap().1).collect();
+        assert_eq!(rest, vec![Pose { x: 0, y: 1 }, Pose { x: 5, y: 1 }]);
+    }
+
+    #[test]
+    fn test_datastore_structs_glob() {
+        let mut datastore = Datastore::new();
+        for i in 0..3 {
+            datastore
+                .add_struct(
+                    &TopicKey::from_str("robots/a/pose"),
+                    Timepoint::new_secs(i as f64),
+                    Pose { x: i, y: 0 },
+                )
+                .unwrap();
+        }
+        datastore
+            .add_primitive(
+                &TopicKey::from_str("robots/b/twist"),
+                Timepoint::new_secs(1.5),
+                1.into(),
+            )
+            .unwrap();
+
+        let glob = TopicKey::from_str("robots/*/pose");
+        let pose: Option<Pose> = datastore
+            .get_struct_at(&glob, &Timepoint::new_secs(1.5))
+            .unwrap();
+        assert_eq!(pose, Some(Pose { x: 1, y: 0 }));
+
+        let range = TimeRange::new(Timepoint::zero(), Timepoint::new_secs(10.0));
+        let structs: Vec<(Timepoint, Pose)> = datastore.get_structs_range(&glob, &range).unwrap();
+        assert_eq!(structs.len(), 3);
+        assert_eq!(structs[2], (Timepoint::new_secs(2.0), Pose { x: 2, y: 0 }));
+    }
+}
@@ -1,7 +1,9 @@
 use serde::{Deserialize, Serialize};
-use std::fmt;
+use std::{collections::HashMap, fmt};
 use victory_wtf::Timespan;
 
+use crate::topics::{TopicKey, TopicKeyHandle, TopicKeyProvider};
+
 #[derive(Clone, Default, Serialize, Deserialize)]
 pub struct RetentionPolicy {
     pub max_age: Option<Timespan>,
@@ -17,3 +19,88 @@ impl fmt::Debug for RetentionPolicy {
         )
     }
 }
+
+/// Table of retention policies keyed by topic prefix.
+/// A topic uses the policy of the longest prefix it falls under, or `default` if none match.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct RetentionRules {
+    pub default: RetentionPolicy,
+    rules: HashMap<TopicKeyHandle, RetentionPolicy>,
+}
+
+impl fmt::Debug for RetentionRules {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[default = {:?}, rules = {:?}]",
+            self.default, self.rules
+        )
+    }
+}
+
+impl RetentionRules {
+    pub fn new(default: RetentionPolicy) -> RetentionRules {
+        RetentionRules {
+            default,
+            rules: HashMap::new(),
+        }
+    }
+
+    /// Set the policy for every topic under (and including) `prefix`
+    pub fn add_rule<T: TopicKeyProvider>(&mut self, prefix: &T, policy: RetentionPolicy) {
+        self.rules.insert(prefix.handle(), policy);
+    }
+
+    pub fn remove_rule<T: TopicKeyProvider>(&mut self, prefix: &T) -> Option<RetentionPolicy> {
+        self.rules.remove(&prefix.handle())
+    }
+
+    pub fn get_rules(&self) -> &HashMap<TopicKeyHandle, RetentionPolicy> {
+        &self.rules
+    }
+
+    /// Resolve the policy for a topic using longest-prefix matching
+    pub fn resolve(&self, topic: &TopicKey) -> &RetentionPolicy {
+        self.rules
+            .iter()
+            .filter(|(prefix, _)| topic.is_child_of(prefix))
+            .max_by_key(|(prefix, _)| prefix.sections.len())
+            .map(|(_, policy)| policy)
+            .unwrap_or(&self.default)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retention_rules_longest_prefix() {
+        let mut rules = RetentionRules::new(RetentionPolicy {
+            max_age: None,
+            max_rows: Some(100),
+        });
+        rules.add_rule(
+            &TopicKey::from_str("sensors"),
+            RetentionPolicy {
+                max_age: None,
+                max_rows: Some(10),
+            },
+        );
+        rules.add_rule(
+            &TopicKey::from_str("sensors/imu"),
+            RetentionPolicy {
+                max_age: None,
+                max_rows: Some(1000),
+            },
+        );
+        rules.add_rule(&TopicKey::from_str("config"), RetentionPolicy::default());
+
+        let resolve = |t: &str| rules.resolve(&TopicKey::from_str(t)).max_rows;
+        assert_eq!(resolve("sensors/imu/accel/x"), Some(1000));
+        assert_eq!(resolve("sensors/imu"), Some(1000));
+        assert_eq!(resolve("sensors/gps/lat"), Some(10));
+        assert_eq!(resolve("config/gains/kp"), None);
+        assert_eq!(resolve("pose/x"), Some(100));
+    }
+}
@@ -0,0 +1,174 @@
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+use victory_wtf::{Timepoint, Timespan};
+
+use crate::{
+    primitives::Primitives,
+    topics::{TopicKeyHandle, TopicKeyProvider},
+};
+
+use super::{Datastore, DatastoreError};
+
+/// How a bucket is sampled between its stored datapoints
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SampleMode {
+    /// Zero-order hold, use the last value at or before the sample time
+    HoldLast,
+    /// Linearly interpolate Integer and Float values, holding the last value for other types
+    Linear,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SampleRow {
+    pub time: Timepoint,
+    /// One value per topic in `SampleGrid::topics`, None before a topic's first datapoint
+    pub values: Vec<Option<Primitives>>,
+}
+
+/// Values of many topics aligned on a common time grid
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SampleGrid {
+    pub topics: Vec<TopicKeyHandle>,
+    pub rows: Vec<SampleRow>,
+}
+
+impl SampleGrid {
+    /// Index of `topic` in each row's values
+    pub fn column<T: TopicKeyProvider>(&self, topic: &T) -> Option<usize> {
+        self.topics.iter().position(|t| t.key() == topic.key())
+    }
+}
+
+/// Interpolate between two numeric primitives, None if they aren't both Integer or Float
+pub(crate) fn lerp_primitives(a: &Primitives, b: &Primitives, alpha: f64) -> Option<Primitives> {
+    match (a, b) {
+        (Primitives::Float(a), Primitives::Float(b)) => {
+            Some(Primitives::Float(a + (b - a) * alpha))
+        }
+        (Primitives::Integer(a), Primitives::Integer(b)) => {
+            let value = *a as f64 + (*b as f64 - *a as f64) * alpha;
+            Some(Primitives::Integer(value.round() as i64))
+        }
+        _ => None,
+    }
+}
+
+impl Datastore {
+    /// Sample every bucket matching `query` at `start`, `start + step`, ... up to and including `end`.
+    /// Topics are sorted by display name.
+    #[instrument(skip_all)]
+    pub fn sample_grid<T: TopicKeyProvider>(
+        &self,
+        query: &T,
+        start: &Timepoint,
+        end: &Timepoint,
+        step: &Timespan,
+        mode: SampleMode,
+    ) -> Result<SampleGrid, DatastoreError> {
+        if step.ns() == 0 {
+            return Err(DatastoreError::Generic(
+                "Sample grid step must be greater than zero".to_string(),
+            ));
+        }
+
+        let mut buckets = self.get_buckets_matching(query)?;
+        buckets.sort_by_key(|b| b.read().unwrap().topic.display_name());
+        let buckets: Vec<_> = buckets.iter().map(|b| b.read().unwrap()).collect();
+
+        let mut rows = Vec::new();
+        let mut time_ns = start.ns();
+        while time_ns <= end.ns() {
+            let time = Timepoint::new_ns(time_ns);
+            let values = buckets
+                .iter()
+                .map(|bucket| bucket.sample(&time, mode))
+                .collect();
+            rows.push(SampleRow { time, values });
+            time_ns += step.ns();
+        }
+
+        Ok(SampleGrid {
+            topics: buckets.iter().map(|b| b.topic.clone()).collect(),
+            rows,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::topics::TopicKey;
+
+    use super::*;
+
+    #[test]
+    fn test_sample_grid() {
+        let mut datastore = Datastore::new();
+        let topic_x: TopicKey = "robot/pose/x".into();
+        let topic_mode: TopicKey = "robot/mode".into();
+        let topic_late: TopicKey = "robot/late".into();
+
+        datastore
+            .add_primitive(&topic_x, Timepoint::new_secs(0.0), 0.0.into())
+            .unwrap();
+        datastore
+            .add_primitive(&topic_x, Timepoint::new_secs(2.0), 4.0.into())
+            .unwrap();
+        datastore
+            .add_primitive(&topic_mode, Timepoint::new_secs(0.0), "idle".into())
+            .unwrap();
+        datastore
+            .add_primitive(&topic_mode, Timepoint::new_secs(1.0), "armed".into())
+            .unwrap();
+        datastore
+            .add_primitive(&topic_late, Timepoint::new_secs(1.5), 10.into())
+            .unwrap();
+        datastore
+            .add_primitive(&topic_late, Timepoint::new_secs(2.5), 20.into())
+            .unwrap();
+
+        let query: TopicKey = "robot".into();
+        let start = Timepoint::new_secs(0.0);
+        let end = Timepoint::new_secs(3.0);
+        let step = Timespan::new_secs(0.5);
+
+        let grid = datastore
+            .sample_grid(&query, &start, &end, &step, SampleMode::HoldLast)
+            .unwrap();
+        assert_eq!(grid.rows.len(), 7);
+        assert_eq!(grid.topics.len(), 3);
+        let x = grid.column(&topic_x).unwrap();
+        let mode = grid.column(&topic_mode).unwrap();
+        let late = grid.column(&topic_late).unwrap();
+
+        assert_eq!(grid.rows[1].values[x], Some(0.0.into()));
+        assert_eq!(grid.rows[4].values[x], Some(4.0.into()));
+        assert_eq!(grid.rows[1].values[mode], Some("idle".into()));
+        assert_eq!(grid.rows[2].values[mode], Some("armed".into()));
+        assert_eq!(grid.rows[2].values[late], None);
+
+        let grid = datastore
+            .sample_grid(&query, &start, &end, &step, SampleMode::Linear)
+            .unwrap();
+        assert_eq!(grid.rows[1].values[x], Some(1.0.into()));
+        assert_eq!(grid.rows[3].values[x], Some(3.0.into()));
+        // Past the last datapoint the value is held
+        assert_eq!(grid.rows[6].values[x], Some(4.0.into()));
+        assert_eq!(grid.rows[4].values[late], Some(15.into()));
+        // Text can't be interpolated so it is held
+        assert_eq!(grid.rows[3].values[mode], Some("armed".into()));
+    }
+
+    #[test]
+    fn test_sample_grid_zero_step() {
+        let datastore = Datastore::new();
+        let query: TopicKey = "robot".into();
+        let result = datastore.sample_grid(
+            &query,
+            &Timepoint::zero(),
+            &Timepoint::new_secs(1.0),
+            &Timespan::zero(),
+            SampleMode::HoldLast,
+        );
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,210 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+    sync::{Arc, RwLock},
+};
+
+use log::info;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::buckets::Bucket;
+
+use super::{retention::RetentionRules, Datastore, DatastoreError};
+
+/// Magic bytes at the start of every snapshot file
+pub const SNAPSHOT_MAGIC: [u8; 8] = *b"VICSNAP\0";
+/// Current snapshot format version, bumped on any breaking layout change
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Metadata written after the magic bytes and version
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotHeader {
+    pub bucket_count: u64,
+    pub retention: RetentionRules,
+}
+
+/// Snapshot layout:
+/// - `SNAPSHOT_MAGIC`
+/// - `SNAPSHOT_VERSION` as little-endian u32
+/// - `SnapshotHeader` (MessagePack)
+/// - `bucket_count` x `Bucket` (MessagePack), each with its topic and values
+impl Datastore {
+    #[instrument(skip_all)]
+    pub fn save_snapshot<P: AsRef<Path>>(&self, path: P) -> Result<(), DatastoreError> {
+        let path = path.as_ref();
+        // Write to a temporary file first so a crash mid-save keeps the previous snapshot
+        let tmp_path = path.with_extension("tmp");
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+
+        writer.write_all(&SNAPSHOT_MAGIC)?;
+        writer.write_all(&SNAPSHOT_VERSION.to_le_bytes())?;
+
+        let header = SnapshotHeader {
+            bucket_count: self.buckets.len() as u64,
+            retention: self.retention.clone(),
+        };
+        rmp_serde::encode::write_named(&mut writer, &header)
+            .map_err(|e| DatastoreError::Snapshot(format!("Error writing header: {:?}", e)))?;
+
+        for bucket in self.buckets.values() {
+            let bucket = bucket.read().unwrap();
+            rmp_serde::encode::write_named(&mut writer, &*bucket).map_err(|e| {
+                DatastoreError::Snapshot(format!(
+                    "Error writing bucket {}: {:?}",
+                    bucket.topic.display_name(),
+                    e
+                ))
+            })?;
+        }
+
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        drop(writer);
+        std::fs::rename(&tmp_path, path)?;
+
+        info!(
+            "[DB/save_snapshot] Saved {} buckets to {:?}",
+            header.bucket_count, path
+        );
+        Ok(())
+    }
+
+    #[instrument(skip_all)]
+    pub fn load_snapshot<P: AsRef<Path>>(path: P) -> Result<Datastore, DatastoreError> {
+        let path = path.as_ref();
+        let mut reader = BufReader::new(File::open(path)?);
+
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if magic != SNAPSHOT_MAGIC {
+            return Err(DatastoreError::Snapshot(format!(
+                "{:?} is not a datastore snapshot",
+                path
+            )));
+        }
+
+        let mut version = [0u8; 4];
+        reader.read_exact(&mut version)?;
+        let version = u32::from_le_bytes(version);
+        if version != SNAPSHOT_VERSION {
+            return Err(DatastoreError::Snapshot(format!(
+                "Unsupported snapshot version {} (expected {})",
+                version, SNAPSHOT_VERSION
+            )));
+        }
+
+        let mut deserializer = rmp_serde::Deserializer::new(&mut reader);
+        let header = SnapshotHeader::deserialize(&mut deserializer)
+            .map_err(|e| DatastoreError::Snapshot(format!("Error reading header: {:?}", e)))?;
+
+        let mut datastore = Datastore::new();
+        datastore.retention = header.retention;
+        for _ in 0..header.bucket_count {
+            let bucket = Bucket::deserialize(&mut deserializer)
+                .map_err(|e| DatastoreError::Snapshot(format!("Error reading bucket: {:?}", e)))?;
+            datastore.insert_bucket(Arc::new(RwLock::new(bucket)));
+        }
+
+        info!(
+            "[DB/load_snapshot] Loaded {} buckets from {:?}",
+            datastore.buckets.len(),
+            path
+        );
+        Ok(datastore)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+    use victory_wtf::{Timepoint, Timespan};
+
+    use crate::{
+        database::{retention::RetentionPolicy, Datastore},
+        topics::TopicKey,
+    };
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+    struct TestStruct {
+        a: i32,
+        b: String,
+        c: Vec<f64>,
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let dir = std::env::temp_dir().join(format!("victory_snapshot_{}", rand::random::<u32>()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("store.snapshot");
+
+        let mut datastore = Datastore::new();
+        datastore.set_retention(RetentionPolicy {
+            max_age: Some(Timespan::new_secs(60.0)),
+            max_rows: None,
+        });
+        let topic: TopicKey = "test/struct".into();
+        for i in 0..10 {
+            let value = TestStruct {
+                a: i,
+                b: format!("value_{}", i),
+                c: vec![i as f64, 0.5],
+            };
+            datastore
+                .add_struct(&topic, Timepoint::new_secs(i as f64), value)
+                .unwrap();
+        }
+
+        datastore.save_snapshot(&path).unwrap();
+        let loaded = Datastore::load_snapshot(&path).unwrap();
+
+        let mut keys = loaded
+            .get_all_display_names()
+            .into_values()
+            .collect::<Vec<_>>();
+        let mut expected = datastore
+            .get_all_display_names()
+            .into_values()
+            .collect::<Vec<_>>();
+        keys.sort();
+        expected.sort();
+        assert_eq!(keys, expected);
+
+        assert_eq!(
+            loaded.get_datapoints(&topic).unwrap().len(),
+            datastore.get_datapoints(&topic).unwrap().len()
+        );
+        let result: TestStruct = loaded.get_struct(&topic).unwrap();
+        assert_eq!(result.a, 9);
+        assert_eq!(
+            loaded.retention.default.max_age,
+            Some(Timespan::new_secs(60.0))
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_snapshot_rejects_bad_header() {
+        let dir = std::env::temp_dir().join(format!("victory_snapshot_{}", rand::random::<u32>()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join("garbage.snapshot");
+        std::fs::write(&path, b"not a snapshot at all").unwrap();
+        assert!(Datastore::load_snapshot(&path).is_err());
+
+        let path = dir.join("future.snapshot");
+        let mut bytes = SNAPSHOT_MAGIC.to_vec();
+        bytes.extend_from_slice(&(SNAPSHOT_VERSION + 1).to_le_bytes());
+        std::fs::write(&path, bytes).unwrap();
+        match Datastore::load_snapshot(&path) {
+            Err(DatastoreError::Snapshot(msg)) => assert!(msg.contains("version")),
+            other => panic!("Expected version error, got {:?}", other.map(|_| ())),
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
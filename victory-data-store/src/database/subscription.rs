@@ -0,0 +1,151 @@
+use std::sync::{Arc, Mutex};
+
+use log::debug;
+use tokio::sync::broadcast;
+use tracing::instrument;
+
+use crate::{buckets::BucketHandle, datapoints::Datapoint, topics::TopicKey};
+
+use super::{
+    listener::{BucketEvent, DataStoreListener, ListenerHandle, ListenerId, UpdateSource},
+    Datastore,
+};
+
+/// Number of datapoints buffered per subscription before slow receivers start lagging
+pub const DEFAULT_SUBSCRIPTION_CAPACITY: usize = 1024;
+
+/// Forwards datapoints from the listener dispatch into a broadcast channel
+struct BroadcastListener {
+    sender: broadcast::Sender<Datapoint>,
+}
+
+impl DataStoreListener for BroadcastListener {
+    fn on_datapoints(&mut self, datapoints: &[Datapoint], _source: UpdateSource) {
+        for datapoint in datapoints {
+            // Only fails once every receiver is gone, the subscription is pruned on the next insert
+            let _ = self.sender.send(datapoint.clone());
+        }
+    }
+
+    fn on_bucket_update(&mut self, _bucket: &BucketHandle, _event: BucketEvent) {}
+}
+
+/// A channel subscription, keeping its listener alive while any receiver exists
+#[derive(Debug, Clone)]
+pub(crate) struct Subscription {
+    id: ListenerId,
+    sender: broadcast::Sender<Datapoint>,
+    _listener: ListenerHandle,
+}
+
+impl Datastore {
+    /// Subscribe to every new datapoint overlapping `topic_query`, which may contain wildcards.
+    /// A receiver that falls more than `DEFAULT_SUBSCRIPTION_CAPACITY` datapoints behind gets
+    /// `RecvError::Lagged` with the number of skipped datapoints.
+    #[instrument(skip(self))]
+    pub fn subscribe(&mut self, topic_query: &TopicKey) -> broadcast::Receiver<Datapoint> {
+        self.subscribe_with_capacity(topic_query, DEFAULT_SUBSCRIPTION_CAPACITY)
+    }
+
+    /// Like `subscribe`, buffering at most `capacity` datapoints per receiver
+    #[instrument(skip(self))]
+    pub fn subscribe_with_capacity(
+        &mut self,
+        topic_query: &TopicKey,
+        capacity: usize,
+    ) -> broadcast::Receiver<Datapoint> {
+        let (sender, receiver) = broadcast::channel(capacity);
+        let listener: ListenerHandle = Arc::new(Mutex::new(BroadcastListener {
+            sender: sender.clone(),
+        }));
+        let id = self.add_listener_weak(topic_query, &listener);
+        self.subscriptions.push(Subscription {
+            id,
+            sender,
+            _listener: listener,
+        });
+        receiver
+    }
+
+    pub fn get_subscription_count(&self) -> usize {
+        self.subscriptions.len()
+    }
+
+    /// Remove subscriptions whose receivers have all been dropped
+    pub(crate) fn prune_subscriptions(&mut self) {
+        let mut closed = Vec::new();
+        self.subscriptions.retain(|subscription| {
+            let open = subscription.sender.receiver_count() > 0;
+            if !open {
+                closed.push(subscription.id);
+            }
+            open
+        });
+        for id in closed {
+            debug!(
+                "[DB/subscribe] Receivers dropped, removing subscription {}",
+                id
+            );
+            self.remove_listener(id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::broadcast::error::{RecvError, TryRecvError};
+    use victory_wtf::Timepoint;
+
+    use super::*;
+
+    fn write(datastore: &mut Datastore, topic: &str, value: i32) {
+        datastore.add_datapoints(vec![Datapoint::new(
+            &TopicKey::from_str(topic),
+            Timepoint::new_secs(value as f64),
+            value.into(),
+        )]);
+    }
+
+    #[tokio::test]
+    async fn test_datastore_subscribe() {
+        let mut datastore = Datastore::new();
+        let mut receiver = datastore.subscribe(&TopicKey::from_str("robots/*/pose"));
+
+        write(&mut datastore, "robots/a/pose/x", 1);
+        write(&mut datastore, "robots/a/twist/x", 2);
+        write(&mut datastore, "robots/b/pose/y", 3);
+
+        let first = receiver.recv().await.unwrap();
+        assert_eq!(first.topic.display_name(), "robots/a/pose/x");
+        let second = receiver.recv().await.unwrap();
+        assert_eq!(second.topic.display_name(), "robots/b/pose/y");
+        assert!(matches!(receiver.try_recv(), Err(TryRecvError::Empty)));
+    }
+
+    #[tokio::test]
+    async fn test_datastore_subscribe_lagged() {
+        let mut datastore = Datastore::new();
+        let mut receiver = datastore.subscribe_with_capacity(&TopicKey::from_str("test"), 2);
+
+        for i in 0..5 {
+            write(&mut datastore, "test/a", i);
+        }
+
+        assert!(matches!(receiver.recv().await, Err(RecvError::Lagged(3))));
+        assert_eq!(receiver.recv().await.unwrap().value, 3.into());
+        assert_eq!(receiver.recv().await.unwrap().value, 4.into());
+    }
+
+    #[test]
+    fn test_datastore_subscribe_dropped() {
+        let mut datastore = Datastore::new();
+        let receiver = datastore.subscribe(&TopicKey::from_str("test"));
+        let _other = datastore.subscribe(&TopicKey::from_str("test"));
+        assert_eq!(datastore.get_subscription_count(), 2);
+
+        drop(receiver);
+        write(&mut datastore, "test/a", 1);
+        assert_eq!(datastore.get_subscription_count(), 1);
+        assert_eq!(datastore.get_listener_count(), 1);
+    }
+}
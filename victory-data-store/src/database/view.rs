@@ -4,7 +4,9 @@ use crate::{
     primitives::{
         serde::{deserializer::PrimitiveDeserializer, serialize::to_map}, Primitives,
     },
-    topics::{TopicKey, TopicKeyHandle, TopicKeyProvider},
+    topics::{
+        pattern::TopicPattern, typed::TypedTopic, TopicKey, TopicKeyHandle, TopicKeyProvider,
+    },
 };
 
 use log::warn;
@@ -12,7 +14,7 @@ use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use victory_wtf::Timepoint;
 use std::collections::HashMap;
 
-use super::{Datastore, DatastoreError};
+use super::{check_struct_type, range::TimeRange, Datastore, DatastoreError};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DataView {
@@ -49,8 +51,9 @@ impl DataView {
         view: &DataView,
         topic: &TopicKey,
     ) -> Result<DataView, DatastoreError> {
+        let pattern = TopicPattern::new(topic);
         for (key, datapoint) in view.maps.iter() {
-            if key.key().is_child_of(topic) || key.key() == topic {
+            if pattern.matches(key) {
                 self.maps.insert(key.clone(), datapoint.clone());
             }
         }
@@ -63,8 +66,9 @@ impl DataView {
         topic: &TopicKey,
         time: &Timepoint,
     ) -> Result<DataView, DatastoreError> {
+        let pattern = TopicPattern::new(topic);
         for (key, datapoint) in view.maps.iter() {
-            if (key.key().is_child_of(topic) || key.key() == topic) && datapoint.time >= *time {
+            if pattern.matches(key) && datapoint.time >= *time {
                 self.maps.insert(key.clone(), datapoint.clone());
             }
         }
@@ -106,11 +110,34 @@ impl DataView {
         }
         Ok(self)
     }
+
+    /// Add the latest datapoint within `range` of every topic under `topic`
+    pub fn add_query_range(
+        mut self,
+        datastore: &mut Datastore,
+        topic: &TopicKey,
+        range: &TimeRange,
+    ) -> Result<DataView, DatastoreError> {
+        let latest = TimeRange::new(range.start.clone(), range.end.clone())
+            .with_limit(1)
+            .reversed();
+        let buckets = datastore.get_buckets_matching_cached(topic)?;
+        for bucket in buckets {
+            let bucket = bucket.read().unwrap();
+            for datapoint in bucket.get_data_points_range(&latest) {
+                let key = datapoint.topic.key().clone();
+                self.maps.insert(key, datapoint.clone());
+            }
+        }
+        Ok(self)
+    }
+
     pub fn remove_query<T: TopicKeyProvider>(&mut self, topic: &T) {
+        let pattern = TopicPattern::new(topic);
         self.maps = self.maps
             .iter()
             .filter_map(|(k, v)| {
-                if !k.key().is_child_of(topic.key()) && k.key() != topic.key() {
+                if !pattern.matches(k) {
                     Some((k.clone(), v.clone()))
                 } else {
                     None
@@ -122,11 +149,12 @@ impl DataView {
         &self,
         topic: &T,
     ) -> Result<HashMap<TopicKey, Datapoint>, DatastoreError> {
+        let pattern = TopicPattern::new(topic);
         let map = self
             .maps
             .iter()
             .filter_map(|(k, v)| {
-                if k.key().is_child_of(topic.key()) || k.key() == topic.key() {
+                if pattern.matches(k) {
                     Some((k.key().clone(), v.clone()))
                 } else {
                     None
@@ -140,17 +168,11 @@ impl DataView {
         &self,
         topic: &T,
     ) -> Result<S, DatastoreError> {
+        let pattern = TopicPattern::new(topic);
         let value_map = self
             .maps
             .iter()
-            .filter_map(|(k, v)| {
-                if k.key().is_child_of(topic.key()) {
-                    let key = k.key().remove_prefix(topic.key().clone()).unwrap();
-                    Some((key.handle(), v.value.clone()))
-                } else {
-                    None
-                }
-            })
+            .filter_map(|(k, v)| Some((pattern.strip_match(k)?.handle(), v.value.clone())))
             .collect::<HashMap<TopicKeyHandle, Primitives>>();
 
         // Deserialize the value map into the struct
@@ -161,6 +183,25 @@ impl DataView {
         result
     }
 
+    /// Latest value of a typed topic, failing if the view's `_type` marker doesn't match `S`
+    pub fn get<S: Serialize + DeserializeOwned>(
+        &self,
+        topic: &TypedTopic<S>,
+    ) -> Result<S, DatastoreError> {
+        let marker = topic.key().add_suffix(&TopicKey::from_str("_type"));
+        let stored = self.maps.get(&marker).map(|d| &d.value);
+        check_struct_type(topic.key(), topic.struct_type(), stored)?;
+        self.get_latest(topic)
+    }
+
+    pub fn set<S: Serialize + DeserializeOwned>(
+        &mut self,
+        topic: &TypedTopic<S>,
+        value: S,
+    ) -> Result<(), DatastoreError> {
+        self.add_latest(topic, value)
+    }
+
     pub fn add_latest<T: TopicKeyProvider, S: Serialize>(
         &mut self,
         topic: &T,
@@ -241,17 +282,12 @@ impl DataView {
         topic: &T,
         time: &Timepoint,
     ) -> Result<Option<S>, DatastoreError> {
+        let pattern = TopicPattern::new(topic);
         let value_map = self
             .maps
             .iter()
-            .filter_map(|(k, v)| {
-                if k.key().is_child_of(topic.key()) && v.time >= *time {
-                    let key = k.key().remove_prefix(topic.key().clone()).unwrap();
-                    Some((key.handle(), v.value.clone()))
-                } else {
-                    None
-                }
-            })
+            .filter(|(_, v)| v.time >= *time)
+            .filter_map(|(k, v)| Some((pattern.strip_match(k)?.handle(), v.value.clone())))
             .collect::<HashMap<TopicKeyHandle, Primitives>>();
 
         if value_map.is_empty() {
@@ -266,6 +302,58 @@ impl DataView {
         result
     }
 
+    /// Datapoints of every topic under `topic` within `range`, ordered by time
+    pub fn get_datapoints_range<T: TopicKeyProvider>(
+        &self,
+        topic: &T,
+        range: &TimeRange,
+    ) -> Vec<Datapoint> {
+        let pattern = TopicPattern::new(topic);
+        let mut datapoints = self
+            .maps
+            .iter()
+            .filter(|(k, v)| pattern.matches(*k) && range.contains(&v.time))
+            .map(|(_, v)| v.clone())
+            .collect();
+        range.order(&mut datapoints);
+        datapoints
+    }
+
+    pub fn get_primitives_range<T: TopicKeyProvider>(
+        &self,
+        topic: &T,
+        range: &TimeRange,
+    ) -> Vec<Primitives> {
+        self.get_datapoints_range(topic, range)
+            .into_iter()
+            .map(|dp| dp.value)
+            .collect()
+    }
+
+    /// Deserialize the fields under `topic` whose datapoints fall within `range`.
+    /// Field names are taken relative to the part of the topic matching `topic`.
+    pub fn get_struct_range<T: TopicKeyProvider, S: DeserializeOwned>(
+        &self,
+        topic: &T,
+        range: &TimeRange,
+    ) -> Result<Option<S>, DatastoreError> {
+        let pattern = TopicPattern::new(topic);
+        let value_map = self
+            .maps
+            .iter()
+            .filter(|(_, v)| range.contains(&v.time))
+            .filter_map(|(k, v)| Some((pattern.strip_match(k)?.handle(), v.value.clone())))
+            .collect::<HashMap<TopicKeyHandle, Primitives>>();
+
+        if value_map.is_empty() {
+            return Ok(None);
+        }
+
+        let mut deserializer = PrimitiveDeserializer::new(&value_map);
+        S::deserialize(&mut deserializer)
+            .map(Some)
+            .map_err(|e| DatastoreError::Generic(format!("Error deserializing struct: {:?}", e)))
+    }
 }
 
 #[cfg(test)]
@@ -275,8 +363,8 @@ mod tests {
     use victory_wtf::Timepoint;
 
     use crate::{
-        database::{view::DataView, Datastore},
-        topics::TopicKey,
+        database::{range::TimeRange, view::DataView, Datastore, DatastoreError},
+        topics::{typed::TypedTopic, TopicKey},
     };
 
     #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
@@ -290,6 +378,25 @@ mod tests {
         c: i32,
         d: String,
     }
+    #[test]
+    pub fn test_dataview_typed_topics() {
+        let topic: TypedTopic<TestStructA> = "/test/topic".into();
+        let value = TestStructA {
+            a: 42,
+            b: "test".to_string(),
+        };
+
+        let mut view = DataView::new();
+        view.set(&topic, value.clone()).unwrap();
+        assert_eq!(view.get(&topic).unwrap(), value);
+
+        let wrong: TypedTopic<TestStructB> = "/test/topic".into();
+        assert!(matches!(
+            view.get(&wrong),
+            Err(DatastoreError::TypeMismatch { .. })
+        ));
+    }
+
     #[test]
     pub fn test_dataview_add_latest() {
         let topic: TopicKey = "/test/topic".into();
@@ -350,5 +457,118 @@ mod tests {
         assert_eq!(result, test_struct_b);
     }
 
+    #[test]
+    pub fn test_dataview_range() {
+        let mut datastore = Datastore::new();
+        let topic: TopicKey = "/test/a".into();
+        for i in 0..5 {
+            let value = TestStructA {
+                a: i,
+                b: format!("test_{}", i),
+            };
+            datastore
+                .add_struct(&topic, Timepoint::new_secs(i as f64), value)
+                .unwrap();
+        }
 
+        let range = TimeRange::new(Timepoint::new_secs(1.0), Timepoint::new_secs(3.0));
+        let view = DataView::new()
+            .add_query_range(&mut datastore, &topic, &range)
+            .unwrap();
+
+        // The view keeps the latest datapoint in range, not the latest overall
+        let result: TestStructA = view.get_latest(&topic).unwrap();
+        assert_eq!(result.a, 2);
+
+        let result: Option<TestStructA> = view.get_struct_range(&topic, &range).unwrap();
+        assert_eq!(result.unwrap().b, "test_2");
+        assert_eq!(view.get_datapoints_range(&topic, &range).len(), 2);
+
+        let later = TimeRange::new(Timepoint::new_secs(3.0), Timepoint::new_secs(10.0));
+        assert!(view.get_primitives_range(&topic, &later).is_empty());
+        let result: Option<TestStructA> = view.get_struct_range(&topic, &later).unwrap();
+        assert!(result.is_none());
+    }
+
+    #[test]
+    pub fn test_dataview_range_glob() {
+        let mut datastore = Datastore::new();
+        for i in 0..3 {
+            let value = TestStructA {
+                a: i,
+                b: format!("test_{}", i),
+            };
+            datastore
+                .add_struct(
+                    &TopicKey::from_str("robots/a/state"),
+                    Timepoint::new_secs(i as f64),
+                    value,
+                )
+                .unwrap();
+            datastore
+                .add_primitive(
+                    &TopicKey::from_str("robots/b/twist"),
+                    Timepoint::new_secs(i as f64),
+                    i.into(),
+                )
+                .unwrap();
+        }
+        let view = DataView::new()
+            .add_query_range(
+                &mut datastore,
+                &TopicKey::from_str("robots"),
+                &TimeRange::new(Timepoint::zero(), Timepoint::new_secs(10.0)),
+            )
+            .unwrap();
+
+        let glob = TopicKey::from_str("robots/*/state");
+        let range = TimeRange::new(Timepoint::new_secs(1.0), Timepoint::new_secs(3.0));
+        let datapoints = view.get_datapoints_range(&glob, &range);
+        assert_eq!(datapoints.len(), 2);
+        assert!(datapoints
+            .iter()
+            .all(|dp| dp.topic.display_name().starts_with("robots/a/state")));
+
+        let result: TestStructA = view.get_struct_range(&glob, &range).unwrap().unwrap();
+        assert_eq!(result.b, "test_2");
+    }
+
+    #[test]
+    pub fn test_dataview_latest_glob() {
+        let mut datastore = Datastore::new();
+        let value = TestStructA {
+            a: 1,
+            b: "test_1".to_string(),
+        };
+        datastore
+            .add_struct(
+                &TopicKey::from_str("robots/a/state"),
+                Timepoint::new_secs(1.0),
+                value.clone(),
+            )
+            .unwrap();
+        datastore
+            .add_primitive(
+                &TopicKey::from_str("robots/b/twist"),
+                Timepoint::new_secs(1.0),
+                1.into(),
+            )
+            .unwrap();
+        let view = DataView::new()
+            .add_query(&mut datastore, &TopicKey::from_str("robots"))
+            .unwrap();
+
+        let glob = TopicKey::from_str("robots/*/state");
+        let result: TestStructA = view.get_latest(&glob).unwrap();
+        assert_eq!(result, value);
+
+        let result: Option<TestStructA> = view
+            .get_struct_after(&glob, &Timepoint::new_secs(0.5))
+            .unwrap();
+        assert_eq!(result, Some(value));
+        let result: Option<TestStructA> = view
+            .get_struct_after(&glob, &Timepoint::new_secs(2.0))
+            .unwrap();
+        assert_eq!(result, None);
+    }
 }
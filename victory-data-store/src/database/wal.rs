@@ -0,0 +1,482 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufReader, BufWriter, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use log::{debug, info, warn};
+use tracing::instrument;
+
+use crate::datapoints::Datapoint;
+
+use super::{Datastore, DatastoreError};
+
+/// Magic bytes at the start of every log segment
+pub const WAL_MAGIC: [u8; 8] = *b"VICWAL\0\0";
+/// Current log record format version
+pub const WAL_VERSION: u32 = 1;
+
+const WAL_HEADER_LEN: u64 = 12;
+const WAL_SEGMENT_EXTENSION: &str = "wal";
+
+#[derive(Debug, Clone)]
+pub struct WalConfig {
+    /// Directory the segment files are written to
+    pub dir: PathBuf,
+    /// Segment size after which a new segment file is started
+    pub max_segment_bytes: u64,
+}
+
+impl WalConfig {
+    pub fn new<P: AsRef<Path>>(dir: P) -> WalConfig {
+        WalConfig {
+            dir: dir.as_ref().to_path_buf(),
+            max_segment_bytes: 64 * 1024 * 1024,
+        }
+    }
+
+    pub fn with_max_segment_bytes(mut self, max_segment_bytes: u64) -> WalConfig {
+        self.max_segment_bytes = max_segment_bytes;
+        self
+    }
+}
+
+/// Append-only log of accepted datapoints, split into numbered segment files.
+///
+/// Segment layout:
+/// - `WAL_MAGIC`
+/// - `WAL_VERSION` as little-endian u32
+/// - Records of `[len: u32 LE][Datapoint (MessagePack)]`
+#[derive(Debug)]
+pub struct WalWriter {
+    config: WalConfig,
+    segment_index: u64,
+    segment_bytes: u64,
+    writer: BufWriter<File>,
+}
+
+impl WalWriter {
+    /// Open a writer in `config.dir`, always starting a fresh segment after any existing ones
+    #[instrument(skip_all)]
+    pub fn open(config: WalConfig) -> Result<WalWriter, DatastoreError> {
+        std::fs::create_dir_all(&config.dir)?;
+        let segment_index = list_segments(&config.dir)?
+            .last()
+            .map(|(index, _)| index + 1)
+            .unwrap_or(0);
+        let writer = create_segment(&config.dir, segment_index)?;
+        info!(
+            "[DB/WAL] Opened log in {:?} at segment {}",
+            config.dir, segment_index
+        );
+        Ok(WalWriter {
+            config,
+            segment_index,
+            segment_bytes: WAL_HEADER_LEN,
+            writer,
+        })
+    }
+
+    #[instrument(skip_all)]
+    pub fn append(&mut self, datapoints: &[Datapoint]) -> Result<(), DatastoreError> {
+        for datapoint in datapoints {
+            let record = rmp_serde::to_vec_named(datapoint)
+                .map_err(|e| DatastoreError::Wal(format!("Error encoding datapoint: {:?}", e)))?;
+
+            if self.segment_bytes + record.len() as u64 + 4 > self.config.max_segment_bytes
+                && self.segment_bytes > WAL_HEADER_LEN
+            {
+                self.roll()?;
+            }
+
+            self.writer
+                .write_all(&(record.len() as u32).to_le_bytes())?;
+            self.writer.write_all(&record)?;
+            self.segment_bytes += record.len() as u64 + 4;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Flush and fsync the current segment
+    pub fn sync(&mut self) -> Result<(), DatastoreError> {
+        self.writer.flush()?;
+        self.writer.get_ref().sync_data()?;
+        Ok(())
+    }
+
+    pub fn get_segment_index(&self) -> u64 {
+        self.segment_index
+    }
+
+    fn roll(&mut self) -> Result<(), DatastoreError> {
+        self.sync()?;
+        self.segment_index += 1;
+        self.writer = create_segment(&self.config.dir, self.segment_index)?;
+        self.segment_bytes = WAL_HEADER_LEN;
+        debug!("[DB/WAL] Rolled to segment {}", self.segment_index);
+        Ok(())
+    }
+}
+
+fn segment_path(dir: &Path, index: u64) -> PathBuf {
+    dir.join(format!("segment_{:08}.{}", index, WAL_SEGMENT_EXTENSION))
+}
+
+fn create_segment(dir: &Path, index: u64) -> Result<BufWriter<File>, DatastoreError> {
+    let file = OpenOptions::new()
+        .create_new(true)
+        .write(true)
+        .open(segment_path(dir, index))?;
+    let mut writer = BufWriter::new(file);
+    writer.write_all(&WAL_MAGIC)?;
+    writer.write_all(&WAL_VERSION.to_le_bytes())?;
+    writer.flush()?;
+    Ok(writer)
+}
+
+/// List segment files in `dir`, sorted by segment index
+pub fn list_segments(dir: &Path) -> Result<Vec<(u64, PathBuf)>, DatastoreError> {
+    let mut segments = Vec::new();
+    if !dir.exists() {
+        return Ok(segments);
+    }
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some(WAL_SEGMENT_EXTENSION) {
+            continue;
+        }
+        let index = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .and_then(|s| s.strip_prefix("segment_"))
+            .and_then(|s| s.parse::<u64>().ok());
+        if let Some(index) = index {
+            segments.push((index, path));
+        }
+    }
+    segments.sort_by_key(|(index, _)| *index);
+    Ok(segments)
+}
+
+/// Read every record of every segment in `dir` in order.
+/// A torn record at the end of the final segment is truncated away, anything
+/// else that fails to decode is an error.
+#[instrument(skip_all)]
+pub fn read_segments<F>(dir: &Path, mut on_datapoint: F) -> Result<usize, DatastoreError>
+where
+    F: FnMut(Datapoint),
+{
+    let segments = list_segments(dir)?;
+    let mut count = 0;
+    for (i, (_, path)) in segments.iter().enumerate() {
+        let is_last = i == segments.len() - 1;
+        count += read_segment(path, is_last, &mut on_datapoint)?;
+    }
+    Ok(count)
+}
+
+fn read_segment<F>(
+    path: &Path,
+    is_last: bool,
+    on_datapoint: &mut F,
+) -> Result<usize, DatastoreError>
+where
+    F: FnMut(Datapoint),
+{
+    let file = File::open(path)?;
+    let file_len = file.metadata()?.len();
+    let mut reader = BufReader::new(file);
+
+    let mut header = [0u8; WAL_HEADER_LEN as usize];
+    if let Err(e) = reader.read_exact(&mut header) {
+        if e.kind() == ErrorKind::UnexpectedEof && is_last {
+            // Removed rather than emptied, the next writer starts a new segment after it
+            warn!("[DB/WAL] Segment {:?} has a torn header, removing", path);
+            std::fs::remove_file(path)?;
+            return Ok(0);
+        }
+        return Err(e.into());
+    }
+    if header[..8] != WAL_MAGIC {
+        return Err(DatastoreError::Wal(format!(
+            "{:?} is not a log segment",
+            path
+        )));
+    }
+    let version = u32::from_le_bytes(header[8..12].try_into().unwrap());
+    if version != WAL_VERSION {
+        return Err(DatastoreError::Wal(format!(
+            "Unsupported log version {} in {:?} (expected {})",
+            version, path, WAL_VERSION
+        )));
+    }
+
+    let mut offset = WAL_HEADER_LEN;
+    let mut count = 0;
+    loop {
+        let mut len = [0u8; 4];
+        match read_fully(&mut reader, &mut len)? {
+            0 => break,
+            4 => {}
+            _ => return torn_record(path, offset, is_last, count),
+        }
+        let len = u32::from_le_bytes(len) as usize;
+        if offset + 4 + len as u64 > file_len {
+            return torn_record(path, offset, is_last, count);
+        }
+
+        let mut record = vec![0u8; len];
+        if read_fully(&mut reader, &mut record)? != len {
+            return torn_record(path, offset, is_last, count);
+        }
+        let datapoint = match rmp_serde::from_slice::<Datapoint>(&record) {
+            Ok(datapoint) => datapoint,
+            Err(_) => return torn_record(path, offset, is_last, count),
+        };
+
+        on_datapoint(datapoint);
+        offset += 4 + len as u64;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Like `read_exact` but returns how many bytes were read before EOF
+fn read_fully<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<usize, DatastoreError> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(read)
+}
+
+fn torn_record(
+    path: &Path,
+    offset: u64,
+    is_last: bool,
+    count: usize,
+) -> Result<usize, DatastoreError> {
+    if !is_last {
+        return Err(DatastoreError::Wal(format!(
+            "Corrupt record at offset {} in {:?}",
+            offset, path
+        )));
+    }
+    warn!(
+        "[DB/WAL] Truncating torn record at offset {} in {:?}",
+        offset, path
+    );
+    truncate(path, offset)?;
+    Ok(count)
+}
+
+fn truncate(path: &Path, len: u64) -> Result<(), DatastoreError> {
+    let file = OpenOptions::new().write(true).open(path)?;
+    file.set_len(len)?;
+    file.sync_all()?;
+    Ok(())
+}
+
+// ----------------------------
+// Datastore Integration
+// ----------------------------
+impl Datastore {
+    /// Start streaming every accepted datapoint to a segmented log in `config.dir`
+    #[instrument(skip_all)]
+    pub fn enable_wal(&mut self, config: WalConfig) -> Result<(), DatastoreError> {
+        let writer = WalWriter::open(config)?;
+        self.wal = Some(Arc::new(Mutex::new(writer)));
+        Ok(())
+    }
+
+    pub fn disable_wal(&mut self) {
+        self.wal = None;
+    }
+
+    /// Replay all log segments in `dir` into this datastore without notifying listeners.
+    /// Should be called before `enable_wal` so replayed points are not logged again.
+    ///
+    /// Logged datapoints were already accepted once, so they are restored as-is instead of
+    /// going through the bucket's dedup and reorder checks again. Returns the number of
+    /// datapoints applied, records already present in the datastore are not counted.
+    #[instrument(skip_all)]
+    pub fn replay_wal<P: AsRef<Path>>(&mut self, dir: P) -> Result<usize, DatastoreError> {
+        let dir = dir.as_ref();
+        let mut applied = 0;
+        let read = read_segments(dir, |datapoint| {
+            let bucket = self.get_or_create_bucket(&datapoint.topic);
+            if bucket.write().unwrap().restore(datapoint) {
+                applied += 1;
+            }
+        })?;
+        info!(
+            "[DB/WAL] Replayed {} of {} datapoints from {:?}",
+            applied, read, dir
+        );
+        Ok(applied)
+    }
+
+    pub(crate) fn write_wal(&self, datapoints: &[Datapoint]) {
+        if let Some(wal) = &self.wal {
+            if let Err(e) = wal.lock().unwrap().append(datapoints) {
+                warn!(
+                    "[DB/WAL] Failed to append {} datapoints: {}",
+                    datapoints.len(),
+                    e
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use victory_wtf::Timepoint;
+
+    use crate::{buckets::dedup::DedupPolicy, topics::TopicKey};
+
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("victory_wal_{}", rand::random::<u32>()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_wal_replay() {
+        let dir = temp_dir();
+        let topic: TopicKey = "test/topic".into();
+
+        let mut datastore = Datastore::new();
+        datastore
+            .enable_wal(WalConfig::new(&dir).with_max_segment_bytes(256))
+            .unwrap();
+        for i in 0..50 {
+            datastore.add_datapoints(vec![Datapoint::new(
+                &topic,
+                Timepoint::new_secs(i as f64),
+                i.into(),
+            )]);
+        }
+        // Duplicate values are rejected by the bucket and never logged
+        datastore.add_datapoints(vec![Datapoint::new(
+            &topic,
+            Timepoint::new_secs(100.0),
+            49.into(),
+        )]);
+        datastore.disable_wal();
+
+        assert!(
+            list_segments(&dir).unwrap().len() > 1,
+            "Segments should roll by size"
+        );
+
+        let mut recovered = Datastore::new();
+        let count = recovered.replay_wal(&dir).unwrap();
+        assert_eq!(count, 50);
+        assert_eq!(
+            recovered.get_datapoints(&topic).unwrap(),
+            datastore.get_datapoints(&topic).unwrap()
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_wal_replay_keeps_repeats() {
+        let dir = temp_dir();
+        let topic: TopicKey = "test/topic".into();
+
+        let mut datastore = Datastore::new();
+        datastore.set_dedup_policy(&topic, DedupPolicy::KeepAll);
+        datastore.enable_wal(WalConfig::new(&dir)).unwrap();
+        for i in 0..3 {
+            datastore.add_datapoints(vec![Datapoint::new(
+                &topic,
+                Timepoint::new_secs(i as f64),
+                7.into(),
+            )]);
+        }
+        datastore.disable_wal();
+
+        // The recovered bucket has the default policy, which would drop the repeats
+        let mut recovered = Datastore::new();
+        assert_eq!(recovered.replay_wal(&dir).unwrap(), 3);
+        assert_eq!(
+            recovered.get_datapoints(&topic).unwrap(),
+            datastore.get_datapoints(&topic).unwrap()
+        );
+        // Records already in the datastore are not applied again
+        assert_eq!(recovered.replay_wal(&dir).unwrap(), 0);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_wal_truncates_torn_record() {
+        let dir = temp_dir();
+        let topic: TopicKey = "test/topic".into();
+
+        let mut writer = WalWriter::open(WalConfig::new(&dir)).unwrap();
+        let datapoints: Vec<Datapoint> = (0..3)
+            .map(|i| Datapoint::new(&topic, Timepoint::new_secs(i as f64), i.into()))
+            .collect();
+        writer.append(&datapoints).unwrap();
+        drop(writer);
+
+        // Simulate a brown out halfway through writing a record
+        let (_, path) = list_segments(&dir).unwrap().pop().unwrap();
+        let intact_len = std::fs::metadata(&path).unwrap().len();
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&64u32.to_le_bytes()).unwrap();
+        file.write_all(&[0x81, 0xa5]).unwrap();
+        drop(file);
+
+        let mut recovered = Datastore::new();
+        assert_eq!(recovered.replay_wal(&dir).unwrap(), 3);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), intact_len);
+        assert_eq!(recovered.get_datapoints(&topic).unwrap(), datapoints);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_wal_removes_torn_header() {
+        let dir = temp_dir();
+        let topic: TopicKey = "test/topic".into();
+        let datapoint = |i: i64| Datapoint::new(&topic, Timepoint::new_secs(i as f64), i.into());
+
+        let mut writer = WalWriter::open(WalConfig::new(&dir)).unwrap();
+        writer.append(&[datapoint(0)]).unwrap();
+        drop(writer);
+
+        // Simulate a brown out while a new segment header was being written
+        let torn = segment_path(&dir, 1);
+        std::fs::write(&torn, &WAL_MAGIC[..3]).unwrap();
+
+        let mut recovered = Datastore::new();
+        assert_eq!(recovered.replay_wal(&dir).unwrap(), 1);
+        assert!(!torn.exists());
+
+        recovered.enable_wal(WalConfig::new(&dir)).unwrap();
+        recovered.add_datapoints(vec![datapoint(1)]);
+        recovered.disable_wal();
+
+        let mut replayed = Datastore::new();
+        assert_eq!(replayed.replay_wal(&dir).unwrap(), 2);
+        assert_eq!(
+            replayed.get_datapoints(&topic).unwrap(),
+            vec![datapoint(0), datapoint(1)]
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
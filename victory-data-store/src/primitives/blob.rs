@@ -1,3 +1,4 @@
+use memuse::DynamicUsage;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Eq)]
@@ -8,6 +9,17 @@ pub struct VicBlob {
     pub hash: String,
 }
 
+impl DynamicUsage for VicBlob {
+    fn dynamic_usage(&self) -> usize {
+        self.data.dynamic_usage() + self.data_type.dynamic_usage() + self.hash.dynamic_usage()
+    }
+
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        let usage = self.dynamic_usage();
+        (usage, Some(usage))
+    }
+}
+
 impl VicBlob {
     pub fn new(data: Vec<u8>, length: u64, data_type: String, hash: String) -> VicBlob {
         VicBlob {
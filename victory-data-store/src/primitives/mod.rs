@@ -1,5 +1,6 @@
 use ::serde::{Deserialize, Serialize};
 use blob::VicBlob;
+use memuse::DynamicUsage;
 use victory_wtf::{Timepoint, Timespan};
 
 use crate::topics::TopicIDType;
@@ -25,3 +26,19 @@ pub enum Primitives {
     Reference(TopicIDType),
     StructType(String),
 }
+
+impl DynamicUsage for Primitives {
+    fn dynamic_usage(&self) -> usize {
+        match self {
+            Primitives::Text(v) | Primitives::StructType(v) => v.dynamic_usage(),
+            Primitives::Blob(v) => v.dynamic_usage(),
+            Primitives::List(v) => v.dynamic_usage(),
+            _ => 0,
+        }
+    }
+
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        let usage = self.dynamic_usage();
+        (usage, Some(usage))
+    }
+}
@@ -5,7 +5,7 @@ use tracing::instrument;
 
 use crate::{
     primitives::Primitives,
-    topics::{TopicKey, TopicKeyHandle},
+    topics::{TopicKey, TopicKeyHandle, TopicKeySections},
 };
 #[allow(unused_imports)]
 #[allow(unused_variables)]
@@ -56,8 +56,15 @@ impl<'de, 'a> Deserializer<'de> for &'a mut PrimitiveDeserializer<'de> {
                 Primitives::Blob(blob) => visitor.visit_bytes(blob.data.as_slice()),
                 Primitives::List(_) => self.deserialize_seq(visitor),
                 Primitives::Unset => visitor.visit_unit(),
+                // Seen as a `_type` entry when a struct is read through `deserialize_any`
+                Primitives::StructType(name) => visitor.visit_str(name),
                 _ => Err(de::Error::custom("Unsupported primitive type")),
             }
+        } else if !self.has_children(&self.path) {
+            // Nothing was written here, like the content of an adjacently tagged unit variant
+            visitor.visit_unit()
+        } else if self.has_sequence_children(&self.path) {
+            self.deserialize_seq(visitor)
         } else {
             self.deserialize_map(visitor)
         }
@@ -303,7 +310,7 @@ impl<'de, 'a> Deserializer<'de> for &'a mut PrimitiveDeserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        if let Some(_primitive) = self.get_value() {
+        if self.get_value().is_some() || self.has_children(&self.path) {
             visitor.visit_some(self)
         } else {
             visitor.visit_none()
@@ -314,10 +321,10 @@ impl<'de, 'a> Deserializer<'de> for &'a mut PrimitiveDeserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        if let Some(Primitives::Unset) = self.get_value() {
-            visitor.visit_unit()
-        } else {
-            Err(de::Error::custom("Expected unit"))
+        // Missing is accepted too, older maps did not write unit at all
+        match self.get_value() {
+            None | Some(Primitives::Unset) => visitor.visit_unit(),
+            Some(other) => Err(de::Error::custom(format!("Expected unit, got {:?}", other))),
         }
     }
 
@@ -329,11 +336,7 @@ impl<'de, 'a> Deserializer<'de> for &'a mut PrimitiveDeserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        if let Some(Primitives::Unset) = self.get_value() {
-            visitor.visit_unit()
-        } else {
-            Err(de::Error::custom("Expected unit"))
-        }
+        self.deserialize_unit(visitor)
     }
 
     fn deserialize_newtype_struct<V>(
@@ -390,7 +393,15 @@ impl<'de, 'a> Deserializer<'de> for &'a mut PrimitiveDeserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        visitor.visit_str(&self.path.display_name())
+        // Only reached for identifiers stored as values, like an adjacently tagged enum's tag
+        match self.get_value() {
+            Some(Primitives::Text(name)) => visitor.visit_str(name),
+            Some(Primitives::Integer(index)) => visitor.visit_u64(*index as u64),
+            other => Err(de::Error::custom(format!(
+                "Expected identifier, got {:?}",
+                other
+            ))),
+        }
     }
 
     #[instrument(skip_all, name = "PrimitiveDeserializer::deserialize_ignored_any")]
@@ -413,18 +424,63 @@ impl<'de, 'a> de::EnumAccess<'de> for EnumAccessImpl<'a, 'de> {
     where
         V: de::DeserializeSeed<'de>,
     {
-        // The enum variant name is expected to be at the current path
+        // A unit variant is stored as text at the current path
         if let Some(Primitives::Text(variant_name)) = self.de.get_value() {
             let val = seed.deserialize(variant_name.as_str().into_deserializer())?;
-            Ok((val, VariantAccessImpl { de: self.de }))
-        } else {
-            Err(de::Error::custom("Expected enum variant as text"))
+            return Ok((
+                val,
+                VariantAccessImpl {
+                    de: self.de,
+                    variant: None,
+                },
+            ));
+        }
+
+        // Any other variant is the only section below the current path
+        let mut keys = self.de.collect_map_keys(&self.de.path).into_iter();
+        match (keys.next(), keys.next()) {
+            (Some(variant), None) => {
+                let name = variant
+                    .sections
+                    .first()
+                    .ok_or_else(|| de::Error::custom("Expected an enum variant section"))?
+                    .display_name
+                    .as_str();
+                let val = seed.deserialize(name.into_deserializer())?;
+                Ok((
+                    val,
+                    VariantAccessImpl {
+                        de: self.de,
+                        variant: Some(variant),
+                    },
+                ))
+            }
+            _ => Err(de::Error::custom(format!(
+                "Expected a single enum variant at {:?}",
+                self.de.path
+            ))),
         }
     }
 }
 
 struct VariantAccessImpl<'a, 'de> {
     de: &'a mut PrimitiveDeserializer<'de>,
+    /// Section holding the variant data, `None` for a unit variant stored as text
+    variant: Option<TopicKey>,
+}
+
+impl<'a, 'de> VariantAccessImpl<'a, 'de> {
+    fn enter(&mut self) -> Result<(), de::value::Error> {
+        match &self.variant {
+            Some(variant) => {
+                self.de.enter(variant);
+                Ok(())
+            }
+            None => Err(de::Error::custom(
+                "Expected enum variant data, found a unit variant",
+            )),
+        }
+    }
 }
 
 impl<'de, 'a> de::VariantAccess<'de> for VariantAccessImpl<'a, 'de> {
@@ -434,25 +490,31 @@ impl<'de, 'a> de::VariantAccess<'de> for VariantAccessImpl<'a, 'de> {
         Ok(())
     }
     #[instrument(skip_all, name = "VariantAccessImpl::newtype_variant_seed")]
-    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Self::Error>
+    fn newtype_variant_seed<T>(mut self, seed: T) -> Result<T::Value, Self::Error>
     where
         T: de::DeserializeSeed<'de>,
     {
-        // Enter the path to the variant's associated data
-        self.de.enter(&TopicKey::empty()); // Entering empty string to append '/' to path
-        let value = seed.deserialize(&mut *self.de)?;
+        self.enter()?;
+        // Only the `Unset` marker means the data wrote nothing, like `Newtype(None)`
+        let value = if matches!(self.de.get_value(), Some(Primitives::Unset))
+            && !self.de.has_children(&self.de.path)
+        {
+            seed.deserialize(().into_deserializer())?
+        } else {
+            seed.deserialize(&mut *self.de)?
+        };
         self.de.exit();
         Ok(value)
     }
     #[instrument(skip_all, name = "VariantAccessImpl::tuple_variant")]
-    fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    fn tuple_variant<V>(mut self, _len: usize, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        self.de.enter(&TopicKey::empty()); // Entering empty string to append '/' to path
+        self.enter()?;
         let indices = self.de.collect_sequence_indices(&self.de.path);
         let value = visitor.visit_seq(SeqAccessImpl {
-            de: self.de,
+            de: &mut *self.de,
             indices,
             index: 0,
         })?;
@@ -461,17 +523,17 @@ impl<'de, 'a> de::VariantAccess<'de> for VariantAccessImpl<'a, 'de> {
     }
     #[instrument(skip_all, name = "VariantAccessImpl::struct_variant")]
     fn struct_variant<V>(
-        self,
-        _fields: &'static [&'static str],
+        mut self,
+        fields: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        self.de.enter(&TopicKey::empty()); // Entering empty string to append '/' to path
+        self.enter()?;
         let value = visitor.visit_map(StructAccess {
-            de: self.de,
-            fields: _fields.to_vec(),
+            de: &mut *self.de,
+            fields: fields.to_vec(),
             field_index: 0,
         })?;
         self.de.exit();
@@ -484,8 +546,11 @@ impl<'de, 'a> PrimitiveDeserializer<'de> {
     fn collect_sequence_indices(&self, prefix: &TopicKey) -> Vec<usize> {
         let mut indices = Vec::new();
         for key in self.flat_map.keys() {
-            if key.is_child_of(prefix) {
-                let key_idx = key.sections.last().unwrap();
+            // The index is the section right below the prefix, elements may be nested deeper
+            if let Some(key_idx) = key.sections.get(prefix.sections.len()) {
+                if !key.is_child_of(prefix) {
+                    continue;
+                }
                 if let Ok(idx) = key_idx.display_name.parse::<usize>() {
                     trace!("idx: {:?}", idx);
                     indices.push(idx);
@@ -501,8 +566,9 @@ impl<'de, 'a> PrimitiveDeserializer<'de> {
         let mut keys = HashSet::new();
 
         for key in self.flat_map.keys() {
-            if key.is_child_of(prefix) {
-                let remainder = key
+            // The prefix itself holds no key, like the `List` marking an empty map
+            if key.sections.len() > prefix.sections.len() && key.is_child_of(prefix) {
+                let remainder: TopicKeySections = key
                     .sections
                     .iter()
                     .skip(prefix.sections.len())
@@ -516,6 +582,25 @@ impl<'de, 'a> PrimitiveDeserializer<'de> {
         }
         keys
     }
+    #[instrument(skip_all, name = "PrimitiveDeserializer::has_children")]
+    fn has_children(&self, prefix: &TopicKey) -> bool {
+        self.flat_map
+            .keys()
+            .any(|key| key.sections.len() > prefix.sections.len() && key.is_child_of(prefix))
+    }
+    /// Children are exactly `0..n`, how a seq is told apart from a map in `deserialize_any`
+    #[instrument(skip_all, name = "PrimitiveDeserializer::has_sequence_children")]
+    fn has_sequence_children(&self, prefix: &TopicKey) -> bool {
+        let keys = self.collect_map_keys(prefix);
+        let mut indices: Vec<usize> = keys
+            .iter()
+            .filter_map(|key| key.sections.first()?.display_name.parse().ok())
+            .collect();
+        indices.sort_unstable();
+        !keys.is_empty()
+            && indices.len() == keys.len()
+            && indices.iter().enumerate().all(|(i, idx)| i == *idx)
+    }
 }
 
 // Implement MapAccess for structs
@@ -596,8 +681,13 @@ impl<'de, 'a> MapAccess<'de> for MapAccessImpl<'a, 'de> {
     {
         if let Some(key) = self.keys.iter().nth(self.index) {
             self.index += 1;
-            seed.deserialize(key.display_name().into_deserializer())
-                .map(Some)
+            let name = key
+                .sections
+                .first()
+                .ok_or_else(|| de::Error::custom("Expected a map key section"))?
+                .display_name
+                .as_str();
+            seed.deserialize(name.into_deserializer()).map(Some)
         } else {
             Ok(None)
         }
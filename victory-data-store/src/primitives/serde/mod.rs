@@ -12,6 +12,7 @@ mod tests {
     use serialize::to_map;
 
     use super::*;
+    use crate::primitives::Primitives;
 
     #[derive(Serialize, Deserialize, Debug, PartialEq)]
     struct TestSimpleStruct {
@@ -88,4 +89,295 @@ mod tests {
 
         assert_eq!(complex, deserialized);
     }
+
+    #[test]
+    fn test_map_key_with_slash() {
+        let mut map = BTreeMap::new();
+        map.insert(String::from("left/front"), 1);
+        map.insert(String::from("right"), 2);
+
+        let serialized = to_map(&map).unwrap();
+        assert!(serialized
+            .keys()
+            .any(|k| k.sections.len() == 1 && k.display_name() == "left\\/front"));
+
+        let mut deserializer = PrimitiveDeserializer::new(&serialized);
+        let deserialized: BTreeMap<String, i32> =
+            Deserialize::deserialize(&mut deserializer).unwrap();
+        assert_eq!(map, deserialized);
+    }
+
+    fn round_trip<T>(value: &T) -> T
+    where
+        T: Serialize + for<'de> Deserialize<'de>,
+    {
+        let serialized = to_map(value).unwrap();
+        trace!("Round trip serialized {:#?}", serialized);
+        let mut deserializer = PrimitiveDeserializer::new(&serialized);
+        Deserialize::deserialize(&mut deserializer).unwrap()
+    }
+
+    fn names(value: &impl Serialize) -> Vec<String> {
+        let mut names: Vec<String> = to_map(value)
+            .unwrap()
+            .keys()
+            .map(|k| k.display_name())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct UnitStruct;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct NewtypeStruct(i32);
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct TupleStruct(i32, String);
+
+    /// Written with `serialize_bytes`, a plain `Vec<u8>` is a seq
+    #[derive(Debug, PartialEq)]
+    struct Bytes(Vec<u8>);
+
+    impl Serialize for Bytes {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_bytes(&self.0)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Bytes {
+        fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            struct BytesVisitor;
+            impl<'de> serde::de::Visitor<'de> for BytesVisitor {
+                type Value = Bytes;
+                fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                    write!(f, "bytes")
+                }
+                fn visit_bytes<E>(self, v: &[u8]) -> Result<Bytes, E> {
+                    Ok(Bytes(v.to_vec()))
+                }
+                fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Bytes, E> {
+                    Ok(Bytes(v))
+                }
+            }
+            deserializer.deserialize_byte_buf(BytesVisitor)
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum External {
+        Unit,
+        Newtype(i32),
+        NewtypeStruct(TestSimpleStruct),
+        Tuple(i32, String),
+        Struct { x: f64, y: Option<String> },
+        Nested(Box<External>),
+        Optional(Option<i32>),
+        Sparse { y: Option<i32> },
+        Map(BTreeMap<String, i32>),
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    #[serde(tag = "kind")]
+    enum Internal {
+        Unit,
+        Newtype(TestSimpleStruct),
+        Struct { x: i32, y: Option<String> },
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    #[serde(tag = "t", content = "c")]
+    enum Adjacent {
+        Unit,
+        Newtype(i32),
+        Tuple(i32, i32),
+        Struct { a: bool },
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    #[serde(untagged)]
+    enum Untagged {
+        Unit,
+        Number(i64),
+        Pair(i32, String),
+        Named { x: f64, label: String },
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct DataModel {
+        boolean: bool,
+        small: i8,
+        unsigned: u16,
+        large: i64,
+        single: f32,
+        double: f64,
+        character: char,
+        text: String,
+        bytes: Bytes,
+        none: Option<i32>,
+        some: Option<i32>,
+        some_struct: Option<TestSimpleStruct>,
+        some_seq: Option<Vec<i32>>,
+        unit: (),
+        some_unit: Option<()>,
+        unit_struct: UnitStruct,
+        newtype_struct: NewtypeStruct,
+        tuple: (i32, String, bool),
+        tuple_struct: TupleStruct,
+        seq_of_structs: Vec<TestSimpleStruct>,
+        seq_of_seqs: Vec<Vec<i32>>,
+        empty_seq: Vec<i32>,
+        map: BTreeMap<String, Vec<i32>>,
+        empty_map: BTreeMap<String, i32>,
+        unit_variant: External,
+        newtype_variant: External,
+        tuple_variant: External,
+        struct_variant: External,
+        variants: Vec<External>,
+        internal: Internal,
+        adjacent: Adjacent,
+        untagged: Vec<Untagged>,
+    }
+
+    #[test]
+    fn test_data_model_round_trip() {
+        sensible_env_logger::safe_init!();
+        let value = DataModel {
+            boolean: true,
+            small: -3,
+            unsigned: 600,
+            large: i64::MIN,
+            single: 1.5,
+            double: -2.25,
+            character: 'x',
+            text: String::from("text"),
+            bytes: Bytes(vec![1, 2, 3]),
+            none: None,
+            some: Some(4),
+            some_struct: Some(TestSimpleStruct::default()),
+            some_seq: Some(vec![5, 6]),
+            unit: (),
+            some_unit: Some(()),
+            unit_struct: UnitStruct,
+            newtype_struct: NewtypeStruct(9),
+            tuple: (1, String::from("two"), false),
+            tuple_struct: TupleStruct(3, String::from("four")),
+            seq_of_structs: vec![TestSimpleStruct::default(), TestSimpleStruct::default()],
+            seq_of_seqs: vec![vec![], vec![1], vec![2, 3]],
+            empty_seq: Vec::new(),
+            map: BTreeMap::from([
+                (String::from("a"), vec![1, 2]),
+                (String::from("b"), vec![3]),
+            ]),
+            empty_map: BTreeMap::new(),
+            unit_variant: External::Unit,
+            newtype_variant: External::Newtype(5),
+            tuple_variant: External::Tuple(6, String::from("six")),
+            struct_variant: External::Struct { x: 0.5, y: None },
+            variants: vec![
+                External::NewtypeStruct(TestSimpleStruct::default()),
+                External::Nested(Box::new(External::Unit)),
+                External::Nested(Box::new(External::Struct {
+                    x: 1.0,
+                    y: Some(String::from("y")),
+                })),
+            ],
+            internal: Internal::Struct { x: 1, y: None },
+            adjacent: Adjacent::Tuple(7, 8),
+            untagged: vec![
+                Untagged::Unit,
+                Untagged::Number(10),
+                Untagged::Pair(11, String::from("eleven")),
+                Untagged::Named {
+                    x: 12.0,
+                    label: String::from("twelve"),
+                },
+            ],
+        };
+        assert_eq!(round_trip(&value), value);
+    }
+
+    #[test]
+    fn test_enum_layout() {
+        assert_eq!(names(&External::Unit), vec![""]);
+        assert_eq!(names(&External::Newtype(1)), vec!["Newtype"]);
+        assert_eq!(
+            names(&External::Tuple(1, String::new())),
+            vec!["Tuple/0", "Tuple/1"]
+        );
+        assert_eq!(
+            names(&External::Struct { x: 1.0, y: None }),
+            vec!["Struct/x"]
+        );
+        assert_eq!(names(&External::Optional(None)), vec!["Optional"]);
+        assert_eq!(names(&External::Sparse { y: None }), vec!["Sparse"]);
+        assert_eq!(names(&Internal::Unit), vec!["_type", "kind"]);
+        assert_eq!(names(&Adjacent::Newtype(1)), vec!["_type", "c", "t"]);
+        assert_eq!(names(&vec![Vec::<i32>::new()]), vec!["0"]);
+
+        let unit = to_map(&External::Unit).unwrap();
+        assert_eq!(
+            unit.values().next(),
+            Some(&Primitives::Text(String::from("Unit")))
+        );
+    }
+
+    #[test]
+    fn test_enum_round_trip() {
+        sensible_env_logger::safe_init!();
+        for value in [
+            External::Unit,
+            External::Newtype(1),
+            External::NewtypeStruct(TestSimpleStruct::default()),
+            External::Tuple(2, String::from("two")),
+            External::Struct {
+                x: 3.0,
+                y: Some(String::from("three")),
+            },
+            External::Nested(Box::new(External::Tuple(4, String::new()))),
+            External::Optional(None),
+            External::Optional(Some(5)),
+            External::Sparse { y: None },
+            External::Sparse { y: Some(6) },
+            External::Map(BTreeMap::new()),
+            External::Map(BTreeMap::from([(String::from("a"), 7)])),
+        ] {
+            assert_eq!(round_trip(&value), value);
+        }
+        for value in [
+            Internal::Unit,
+            Internal::Newtype(TestSimpleStruct::default()),
+            Internal::Struct {
+                x: 1,
+                y: Some(String::from("y")),
+            },
+        ] {
+            assert_eq!(round_trip(&value), value);
+        }
+        for value in [
+            Adjacent::Unit,
+            Adjacent::Newtype(1),
+            Adjacent::Tuple(2, 3),
+            Adjacent::Struct { a: true },
+        ] {
+            assert_eq!(round_trip(&value), value);
+        }
+        for value in [
+            Untagged::Unit,
+            Untagged::Number(1),
+            Untagged::Pair(2, String::from("two")),
+            Untagged::Named {
+                x: 3.0,
+                label: String::from("three"),
+            },
+        ] {
+            assert_eq!(round_trip(&value), value);
+        }
+
+        let empty_map = BTreeMap::<String, i32>::new();
+        assert_eq!(round_trip(&empty_map), empty_map);
+        let empty_seq = Vec::<i32>::new();
+        assert_eq!(round_trip(&empty_seq), empty_seq);
+    }
 }
@@ -4,7 +4,7 @@ use tracing::instrument;
 
 use crate::{
     primitives::{blob::VicBlob, Primitives},
-    topics::{TopicKey, TopicKeyHandle},
+    topics::{TopicKey, TopicKeyHandle, TopicKeySection},
 };
 
 // Create a global copy for _type using a lazy static
@@ -37,10 +37,38 @@ impl std::error::Error for PrimitiveError {}
 
 pub type PrimitiveResult<T> = Result<T, PrimitiveError>;
 
+/// Flattens a value into one primitive per topic below `prefix`:
+/// - scalars, strings and bytes are stored at the prefix itself, unit and unit structs as `Unset`
+/// - struct fields and map entries go one section deeper, structs also write `_type`
+/// - seq, tuple and tuple struct elements go under their index, `0`, `1`, ...
+/// - empty seqs and maps are stored as an empty `List`, so they are not lost inside a seq
+/// - `None` writes nothing, `Some(v)` and newtype structs write `v` in place
+/// - unit variants are written as `Text(variant)`, other variants write their data under a
+///   section named after the variant, like a map with one entry. A variant whose data writes
+///   nothing, like `Newtype(None)`, stores `Unset` at that section so the variant is not lost
+///
+/// Internally tagged and untagged enums are written by serde as plain structs and values.
 pub struct PrimitiveSerializer {
     pub prefix: TopicKey,
     pub map: Vec<(TopicKeyHandle, Primitives)>,
 }
+
+impl PrimitiveSerializer {
+    /// Marks an empty seq or map at the prefix
+    fn push_empty(&mut self) {
+        self.map
+            .push((self.prefix.clone().into(), Primitives::List(Vec::new())));
+    }
+
+    /// Marks the variant section at the prefix if nothing was written since `start`
+    fn push_variant_marker(&mut self, start: usize) {
+        if self.map.len() == start {
+            self.map
+                .push((self.prefix.clone().into(), Primitives::Unset));
+        }
+    }
+}
+
 #[allow(unused_imports)]
 #[allow(unused_variables)]
 #[instrument(skip_all)]
@@ -154,13 +182,13 @@ impl<'a> ser::Serializer for &'a mut PrimitiveSerializer {
     }
     #[instrument(skip_all)]
     fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
-        // Do nothing for unit types
+        self.map
+            .push((self.prefix.clone().into(), Primitives::Unset));
         Ok(())
     }
     #[instrument(skip_all)]
     fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok, Self::Error> {
-        // Do nothing for unit structs
-        Ok(())
+        self.serialize_unit()
     }
 
     fn serialize_unit_variant(
@@ -197,14 +225,18 @@ impl<'a> ser::Serializer for &'a mut PrimitiveSerializer {
     where
         T: Serialize,
     {
+        let depth = self.prefix.sections.len();
+        let start = self.map.len();
         self.prefix.add_suffix_mut(&TopicKey::from_str(variant));
-        value.serialize(&mut *self)?;
-        self.prefix.sections.pop();
-        Ok(())
+        let result = value.serialize(&mut *self);
+        self.push_variant_marker(start);
+        self.prefix.sections.truncate(depth);
+        result
     }
 
     fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
         Ok(SerializeSeq {
+            depth: self.prefix.sections.len(),
             ser: self,
             index: 0,
         })
@@ -229,18 +261,20 @@ impl<'a> ser::Serializer for &'a mut PrimitiveSerializer {
         variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        let depth = self.prefix.sections.len();
         self.prefix.add_suffix_mut(&TopicKey::from_str(variant));
 
         Ok(SerializeSeq {
             ser: self,
             index: 0,
+            depth,
         })
     }
 
     fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
         Ok(SerializeMap {
             ser: self,
-            //key: None,
+            entries: 0,
         })
     }
     #[instrument(skip_all)]
@@ -268,12 +302,14 @@ impl<'a> ser::Serializer for &'a mut PrimitiveSerializer {
         _len: usize,
     ) -> Result<Self::SerializeStructVariant, Self::Error> {
         let current_prefix = self.prefix.clone();
+        let start = self.map.len();
 
         self.prefix.add_suffix_mut(&TopicKey::from_str(variant));
 
         Ok(SerializeStructVariant {
             ser: self,
             original_prefix: current_prefix,
+            start,
         })
     }
 }
@@ -282,6 +318,8 @@ impl<'a> ser::Serializer for &'a mut PrimitiveSerializer {
 pub struct SerializeSeq<'a> {
     ser: &'a mut PrimitiveSerializer,
     index: usize,
+    /// Prefix length to restore on `end`, below the variant section for tuple variants
+    depth: usize,
 }
 
 impl<'a> ser::SerializeSeq for SerializeSeq<'a> {
@@ -302,6 +340,10 @@ impl<'a> ser::SerializeSeq for SerializeSeq<'a> {
     }
 
     fn end(self) -> Result<(), PrimitiveError> {
+        if self.index == 0 {
+            self.ser.push_empty();
+        }
+        self.ser.prefix.sections.truncate(self.depth);
         Ok(())
     }
 }
@@ -352,14 +394,14 @@ impl<'a> ser::SerializeTupleVariant for SerializeSeq<'a> {
     }
     #[instrument(skip_all, name = "SerializeTupleVariant::end")]
     fn end(self) -> Result<(), PrimitiveError> {
-        self.ser.prefix.sections.pop();
-        Ok(())
+        ser::SerializeSeq::end(self)
     }
 }
 
 // Helper struct for serializing maps
 pub struct SerializeMap<'a> {
     ser: &'a mut PrimitiveSerializer,
+    entries: usize,
 }
 
 impl<'a> ser::SerializeMap for SerializeMap<'a> {
@@ -384,10 +426,14 @@ impl<'a> ser::SerializeMap for SerializeMap<'a> {
     {
         value.serialize(&mut *self.ser)?;
         self.ser.prefix.sections.pop();
+        self.entries += 1;
         Ok(())
     }
 
     fn end(self) -> Result<(), PrimitiveError> {
+        if self.entries == 0 {
+            self.ser.push_empty();
+        }
         Ok(())
     }
 }
@@ -426,6 +472,8 @@ impl<'a> ser::SerializeStruct for SerializeStruct<'a> {
 pub struct SerializeStructVariant<'a> {
     ser: &'a mut PrimitiveSerializer,
     original_prefix: TopicKey,
+    /// Map length before the variant, to tell if any field was written
+    start: usize,
 }
 
 impl<'a> ser::SerializeStructVariant for SerializeStructVariant<'a> {
@@ -449,6 +497,7 @@ impl<'a> ser::SerializeStructVariant for SerializeStructVariant<'a> {
     }
     #[instrument(skip_all, name = "SerializeStructVariant::end")]
     fn end(self) -> Result<(), PrimitiveError> {
+        self.ser.push_variant_marker(self.start);
         self.ser.prefix = self.original_prefix.clone();
         Ok(())
     }
@@ -480,7 +529,9 @@ impl<'a> ser::Serializer for &'a mut KeySerializer {
     type SerializeStructVariant = Impossible<(), PrimitiveError>;
     #[instrument(skip_all, name = "KeySerializer::serialize_str")]
     fn serialize_str(self, value: &str) -> Result<(), PrimitiveError> {
-        self.key = TopicKey::from_str(value);
+        // One section even if the key contains `/`, it is escaped when displayed
+        self.key =
+            TopicKey::from_existing(&[TopicKeySection::new_generate(value).into_handle()][..]);
         Ok(())
     }
 
@@ -1,19 +1,50 @@
 use std::{
-    hash::{DefaultHasher, Hash, Hasher},
+    hash::{Hash, Hasher},
     sync::Arc,
 };
 
+use parse::escape_section;
+use registry::StableHasher;
 use serde::{Deserialize, Serialize};
+use smallvec::SmallVec;
 use tracing::instrument;
 
+pub mod parse;
+pub mod pattern;
+pub mod registry;
+pub mod tree;
+pub mod typed;
+
 pub type TopicIDType = u64;
 
+/// Sections are re-interned by name when deserialized, so the id always comes from this
+/// process's registry rather than trusting the one that was sent.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "SerializedSection")]
 pub struct TopicKeySection {
     pub id: TopicIDType,
     pub display_name: String,
 }
+
+#[derive(Deserialize)]
+struct SerializedSection {
+    #[allow(dead_code)]
+    id: TopicIDType,
+    display_name: String,
+}
+
+impl From<SerializedSection> for TopicKeySection {
+    fn from(value: SerializedSection) -> Self {
+        TopicKeySection {
+            id: registry::intern_section(&value.display_name),
+            display_name: value.display_name,
+        }
+    }
+}
 pub type TopicKeySectionHandle = Arc<TopicKeySection>;
+/// Keys up to this many sections deep are stored inline, without a heap allocation
+pub const INLINE_SECTIONS: usize = 6;
+pub type TopicKeySections = SmallVec<[TopicKeySectionHandle; INLINE_SECTIONS]>;
 impl Hash for TopicKeySection {
     #[instrument(skip_all)]
     fn hash<H: Hasher>(&self, state: &mut H) {
@@ -34,20 +65,19 @@ impl TopicKeySection {
     pub fn into_handle(self) -> TopicKeySectionHandle {
         Arc::new(self)
     }
+    /// Id from the global registry, always the stable hash of the name.
+    /// Panics if a different name already has that hash.
     #[instrument(skip_all)]
     pub fn new_generate(display_name: &str) -> TopicKeySection {
-        let mut hasher = DefaultHasher::new();
-        display_name.hash(&mut hasher);
-        let id = hasher.finish() as TopicIDType;
         TopicKeySection {
-            id,
+            id: registry::intern_section(display_name),
             display_name: display_name.to_string(),
         }
     }
 }
 #[derive(Clone, Eq, Serialize, Deserialize)]
 pub struct TopicKey {
-    pub sections: Vec<TopicKeySectionHandle>,
+    pub sections: TopicKeySections,
 }
 
 // Implement string formatting / printing (dispaly name)
@@ -74,10 +104,20 @@ impl Hash for TopicKey {
     }
 }
 
+/// Orders section by section on the names, only comparing strings where the ids differ.
+///
+/// A topic sorts right before its children, so `a/b` comes before `a-b` even though
+/// `"a-b" < "a/b"` as strings. `BTreeMap`s keyed by topic, like `DatapointMap`, iterate
+/// each subtree together.
 impl Ord for TopicKey {
     #[instrument(skip_all)]
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.display_name().cmp(&other.display_name())
+        for (a, b) in self.sections.iter().zip(other.sections.iter()) {
+            if a.id != b.id {
+                return a.display_name.cmp(&b.display_name).then(a.id.cmp(&b.id));
+            }
+        }
+        self.sections.len().cmp(&other.sections.len())
     }
 }
 
@@ -126,9 +166,13 @@ impl TopicKeyProvider for TopicKey {
 }
 
 impl TopicKey {
+    /// Lenient parse that skips empty sections, see `TopicKey::parse` for the strict version
     #[instrument(skip_all)]
     pub fn from_str(display_name: &str) -> TopicKey {
-        let sections: Vec<TopicKeySectionHandle> = display_name
+        if display_name.contains('\\') {
+            return TopicKey::parse_lenient(display_name);
+        }
+        let sections: TopicKeySections = display_name
             .split('/')
             .filter(|s| !s.is_empty())
             .map(|s| TopicKeySection::new_generate(s).into_handle())
@@ -137,13 +181,17 @@ impl TopicKey {
     }
 
     #[instrument(skip_all)]
-    pub fn from_existing(sections: Vec<TopicKeySectionHandle>) -> TopicKey {
-        TopicKey { sections }
+    pub fn from_existing<S: Into<TopicKeySections>>(sections: S) -> TopicKey {
+        TopicKey {
+            sections: sections.into(),
+        }
     }
 
     #[instrument(skip_all)]
     pub fn empty() -> TopicKey {
-        TopicKey { sections: vec![] }
+        TopicKey {
+            sections: TopicKeySections::new(),
+        }
     }
     #[instrument(skip_all)]
     pub fn display_name(&self) -> String {
@@ -162,7 +210,7 @@ impl TopicKey {
             } else {
                 result.push('/');
             }
-            result.push_str(&section.display_name);
+            result.push_str(&escape_section(&section.display_name));
         }
         result
     }
@@ -197,8 +245,9 @@ impl TopicKey {
             return None;
         }
 
-        let sections = self.sections[prefix.sections.len()..].to_vec();
-        Some(TopicKey::from_existing(sections))
+        Some(TopicKey::from_existing(
+            &self.sections[prefix.sections.len()..],
+        ))
     }
 
     pub fn add_suffix_owned(mut self, suffix: TopicKey) -> TopicKey {
@@ -215,13 +264,14 @@ impl TopicKey {
 
     #[instrument(skip_all)]
     pub fn add_suffix_mut(&mut self, suffix: &TopicKey) {
-        self.sections.extend(suffix.sections.clone());
+        self.sections.extend(suffix.sections.iter().cloned());
     }
 
     #[instrument(skip_all)]
     pub fn remove_suffix(&self, suffix: &TopicKey) -> Option<TopicKey> {
-        let sections = self.sections[..self.sections.len() - suffix.sections.len()].to_vec();
-        Some(TopicKey::from_existing(sections))
+        Some(TopicKey::from_existing(
+            &self.sections[..self.sections.len() - suffix.sections.len()],
+        ))
     }
 
     #[instrument(skip_all)]
@@ -231,7 +281,7 @@ impl TopicKey {
         }
 
         for i in 0..parent.sections.len() {
-            if self.sections[i] != parent.sections[i] {
+            if self.sections[i].id != parent.sections[i].id {
                 return false;
             }
         }
@@ -251,9 +301,10 @@ impl TopicKey {
         self.is_child_of(other) || self == other || other.is_child_of(self)
     }
 
+    /// Stable hash of the section ids, see `registry::TopicId` for a collision free id
     #[instrument(skip_all, name = "TopicKey::id")]
     pub fn id(&self) -> TopicIDType {
-        let mut hasher = DefaultHasher::new();
+        let mut hasher = StableHasher::default();
         for section in &self.sections {
             section.id.hash(&mut hasher);
         }
@@ -293,6 +344,41 @@ mod tests {
         assert_eq!(key.sections[0].id, key.sections[1].id);
     }
     #[test]
+    fn test_topic_section_deserialize() {
+        let section = TopicKeySection::new_existing(1234, "arm".to_string());
+        let bytes = rmp_serde::to_vec(&section).unwrap();
+        let section: TopicKeySection = rmp_serde::from_slice(&bytes).unwrap();
+        assert_eq!(section, TopicKeySection::new_generate("arm"));
+    }
+    #[test]
+    fn test_topic_key_ord() {
+        let mut keys: Vec<TopicKey> = ["b", "a/c", "a/b/z", "a", "a/b", "a/b"]
+            .iter()
+            .map(|s| TopicKey::from_str(s))
+            .collect();
+        keys.sort();
+        keys.dedup();
+        let names: Vec<String> = keys.iter().map(|k| k.display_name()).collect();
+        assert_eq!(names, vec!["a", "a/b", "a/b/z", "a/c", "b"]);
+
+        // Children come right after their parent, before siblings sharing a name prefix
+        let mut keys: Vec<TopicKey> = ["ab", "a-b", "a/b", "a", "a/b-c", "a/b/c"]
+            .iter()
+            .map(|s| TopicKey::from_str(s))
+            .collect();
+        keys.sort();
+        let names: Vec<String> = keys.iter().map(|k| k.display_name()).collect();
+        assert_eq!(names, vec!["a", "a/b", "a/b/c", "a/b-c", "a-b", "ab"]);
+
+        let deep = TopicKey::from_str("a/b/c/d/e/f/g/h/i");
+        assert!(deep.sections.spilled());
+        assert!(!TopicKey::from_str("a/b/c").sections.spilled());
+        assert_eq!(
+            deep.remove_prefix("a/b".into()).unwrap().display_name(),
+            "c/d/e/f/g/h/i"
+        );
+    }
+    #[test]
     fn test_topic_key_display() {
         let key = TopicKey::from_str("test/test");
         assert_eq!(key.display_name(), "test/test");
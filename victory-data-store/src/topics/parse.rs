@@ -0,0 +1,218 @@
+use std::borrow::Cow;
+
+use thiserror::Error;
+
+use super::{TopicKey, TopicKeySection, TopicKeySections};
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum TopicParseError {
+    #[error("Empty section at byte {position} of '{topic}'")]
+    EmptySection { topic: String, position: usize },
+    #[error("Invalid escape at byte {position} of '{topic}', only \\/ and \\\\ are allowed")]
+    InvalidEscape { topic: String, position: usize },
+    #[error("Relative section '{section}' in '{topic}', resolve it against a base key instead")]
+    RelativeSection { topic: String, section: String },
+    #[error("'{relative}' goes above the root of '{base}'")]
+    AboveRoot { base: String, relative: String },
+}
+
+/// Escape `/` and `\` so a section name can be written into a topic string
+pub fn escape_section(name: &str) -> Cow<'_, str> {
+    if !name.contains(['/', '\\']) {
+        return Cow::Borrowed(name);
+    }
+    let mut escaped = String::with_capacity(name.len() + 2);
+    for c in name.chars() {
+        if c == '/' || c == '\\' {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    Cow::Owned(escaped)
+}
+
+/// Split `topic` on unescaped `/`, unescaping each section. A single leading and trailing
+/// slash are dropped. Strict mode rejects empty sections and unknown escapes, otherwise
+/// empty sections are skipped and unknown escapes kept as written.
+fn split_sections(topic: &str, strict: bool) -> Result<Vec<String>, TopicParseError> {
+    let mut sections = Vec::new();
+    let mut starts = Vec::new();
+    let mut current = String::new();
+    let mut start = 0;
+    let mut chars = topic.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '/' => {
+                sections.push(std::mem::take(&mut current));
+                starts.push(start);
+                start = i + 1;
+            }
+            '\\' => match chars.next() {
+                Some((_, escaped @ ('/' | '\\'))) => current.push(escaped),
+                _ if strict => {
+                    return Err(TopicParseError::InvalidEscape {
+                        topic: topic.to_string(),
+                        position: i,
+                    });
+                }
+                other => {
+                    current.push('\\');
+                    current.extend(other.map(|(_, c)| c));
+                }
+            },
+            _ => current.push(c),
+        }
+    }
+    sections.push(current);
+    starts.push(start);
+
+    let last = sections.len() - 1;
+    let mut result = Vec::with_capacity(sections.len());
+    for (i, (section, start)) in sections.into_iter().zip(starts).enumerate() {
+        if !section.is_empty() {
+            result.push(section);
+        } else if strict && i != 0 && i != last {
+            return Err(TopicParseError::EmptySection {
+                topic: topic.to_string(),
+                position: start,
+            });
+        }
+    }
+    Ok(result)
+}
+
+fn to_key(sections: impl IntoIterator<Item = String>) -> TopicKey {
+    TopicKey::from_existing(
+        sections
+            .into_iter()
+            .map(|s| TopicKeySection::new_generate(&s).into_handle())
+            .collect::<TopicKeySections>(),
+    )
+}
+
+impl TopicKey {
+    /// Parse a topic, rejecting empty sections (`a//b`), unknown escapes and `.` or `..`.
+    /// `/a/b/`, `a/b/` and `a/b` are the same key, `\/` is a slash within a section.
+    pub fn parse(topic: &str) -> Result<TopicKey, TopicParseError> {
+        let sections = split_sections(topic, true)?;
+        if let Some(section) = sections.iter().find(|s| *s == "." || *s == "..") {
+            return Err(TopicParseError::RelativeSection {
+                topic: topic.to_string(),
+                section: section.clone(),
+            });
+        }
+        Ok(to_key(sections))
+    }
+
+    /// Lenient parse used by `from_str`, never fails
+    pub(crate) fn parse_lenient(topic: &str) -> TopicKey {
+        to_key(split_sections(topic, false).unwrap_or_default())
+    }
+
+    /// Resolve `relative` against this key as a directory. `..` moves up a level and `.`
+    /// stays, a leading `/` makes `relative` absolute.
+    pub fn resolve(&self, relative: &str) -> Result<TopicKey, TopicParseError> {
+        if relative.starts_with('/') {
+            return TopicKey::parse(relative);
+        }
+        let mut sections = self.sections.clone();
+        for section in split_sections(relative, true)? {
+            match section.as_str() {
+                "." => {}
+                ".." => {
+                    if sections.pop().is_none() {
+                        return Err(TopicParseError::AboveRoot {
+                            base: self.display_name(),
+                            relative: relative.to_string(),
+                        });
+                    }
+                }
+                _ => sections.push(TopicKeySection::new_generate(&section).into_handle()),
+            }
+        }
+        Ok(TopicKey::from_existing(sections))
+    }
+}
+
+impl std::str::FromStr for TopicKey {
+    type Err = TopicParseError;
+
+    fn from_str(topic: &str) -> Result<Self, Self::Err> {
+        TopicKey::parse(topic)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(key: &TopicKey) -> Vec<&str> {
+        key.sections
+            .iter()
+            .map(|s| s.display_name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_canonical() {
+        let key = TopicKey::parse("test/topic").unwrap();
+        assert_eq!(TopicKey::parse("/test/topic").unwrap(), key);
+        assert_eq!(TopicKey::parse("/test/topic/").unwrap(), key);
+        assert_eq!(TopicKey::from_str("//test//topic"), key);
+        assert_eq!(TopicKey::parse("").unwrap(), TopicKey::empty());
+        assert_eq!(TopicKey::parse("/").unwrap(), TopicKey::empty());
+        assert_eq!("test/topic".parse::<TopicKey>().unwrap(), key);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            TopicKey::parse("a//b"),
+            Err(TopicParseError::EmptySection {
+                topic: "a//b".to_string(),
+                position: 2
+            })
+        );
+        assert!(matches!(
+            TopicKey::parse("//a"),
+            Err(TopicParseError::EmptySection { position: 1, .. })
+        ));
+        assert!(matches!(
+            TopicKey::parse("a/\\x"),
+            Err(TopicParseError::InvalidEscape { position: 2, .. })
+        ));
+        assert!(matches!(
+            TopicKey::parse("a\\"),
+            Err(TopicParseError::InvalidEscape { .. })
+        ));
+        assert!(matches!(
+            TopicKey::parse("a/../b"),
+            Err(TopicParseError::RelativeSection { .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_escapes() {
+        let key = TopicKey::parse("maps/a\\/b/c\\\\d").unwrap();
+        assert_eq!(names(&key), vec!["maps", "a/b", "c\\d"]);
+        assert_eq!(key.display_name(), "maps/a\\/b/c\\\\d");
+        assert_eq!(TopicKey::parse(&key.display_name()).unwrap(), key);
+        assert_eq!(TopicKey::from_str(&key.display_name()), key);
+        assert_eq!(escape_section("plain"), Cow::Borrowed("plain"));
+    }
+
+    #[test]
+    fn test_resolve() {
+        let base = TopicKey::from_str("robot/arm/controller");
+        let resolve = |relative| base.resolve(relative).unwrap().display_name();
+        assert_eq!(resolve("setpoint"), "robot/arm/controller/setpoint");
+        assert_eq!(resolve("../sibling"), "robot/arm/sibling");
+        assert_eq!(resolve("./a/../b"), "robot/arm/controller/b");
+        assert_eq!(resolve("../../../x"), "x");
+        assert_eq!(resolve("/abs/topic"), "abs/topic");
+        assert!(matches!(
+            base.resolve("../../../.."),
+            Err(TopicParseError::AboveRoot { .. })
+        ));
+    }
+}
@@ -0,0 +1,237 @@
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+
+use super::{TopicIDType, TopicKey, TopicKeyHandle, TopicKeyProvider, TopicKeySection};
+
+/// Matches exactly one section
+pub const WILDCARD_SECTION: &str = "*";
+/// Matches zero or more sections
+pub const WILDCARD_DEPTH: &str = "**";
+
+lazy_static! {
+    static ref WILDCARD_SECTION_ID: TopicIDType =
+        TopicKeySection::new_generate(WILDCARD_SECTION).id;
+    static ref WILDCARD_DEPTH_ID: TopicIDType = TopicKeySection::new_generate(WILDCARD_DEPTH).id;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum PatternElement {
+    Section(TopicIDType),
+    AnySection,
+    AnyDepth,
+}
+
+/// A topic query compiled into a matcher, e.g. `robots/*/pose/x` or `sensors/**/temperature`.
+///
+/// Like plain topic queries, a pattern selects whole subtrees: `robots/*/pose` matches
+/// `robots/a/pose` as well as `robots/a/pose/x`. A query without wildcards behaves exactly
+/// like `TopicKey::is_child_of`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TopicPattern {
+    query: TopicKeyHandle,
+    elements: Vec<PatternElement>,
+}
+
+impl TopicPattern {
+    pub fn new<T: TopicKeyProvider>(query: &T) -> TopicPattern {
+        let elements = query
+            .key()
+            .sections
+            .iter()
+            .map(|section| match section.id {
+                id if id == *WILDCARD_SECTION_ID => PatternElement::AnySection,
+                id if id == *WILDCARD_DEPTH_ID => PatternElement::AnyDepth,
+                id => PatternElement::Section(id),
+            })
+            .collect();
+        TopicPattern {
+            query: query.handle(),
+            elements,
+        }
+    }
+
+    /// True if the topic contains any `*` or `**` sections
+    pub fn is_pattern<T: TopicKeyProvider>(topic: &T) -> bool {
+        topic
+            .key()
+            .sections
+            .iter()
+            .any(|s| s.id == *WILDCARD_SECTION_ID || s.id == *WILDCARD_DEPTH_ID)
+    }
+
+    /// The query this pattern was compiled from
+    pub fn query(&self) -> &TopicKeyHandle {
+        &self.query
+    }
+
+    pub fn elements(&self) -> &[PatternElement] {
+        &self.elements
+    }
+
+    pub fn is_literal(&self) -> bool {
+        self.elements
+            .iter()
+            .all(|e| matches!(e, PatternElement::Section(_)))
+    }
+
+    /// Leading sections before the first wildcard. Every match lives under this prefix.
+    pub fn literal_prefix(&self) -> TopicKey {
+        let len = self
+            .elements
+            .iter()
+            .take_while(|e| matches!(e, PatternElement::Section(_)))
+            .count();
+        TopicKey::from_existing(self.query.sections[..len].to_vec())
+    }
+
+    /// Run the pattern over `topic`, returning (matched_len, pattern_remaining).
+    /// `matched_len` is the length of the shortest prefix of the topic that consumes the
+    /// whole pattern, `pattern_remaining` if the topic ran out while the pattern could
+    /// still continue.
+    fn run(&self, topic: &TopicKey) -> (Option<usize>, bool) {
+        let end = self.elements.len();
+        let mut states = vec![false; end + 1];
+        states[0] = true;
+        self.close(&mut states);
+
+        for (consumed, section) in topic.sections.iter().enumerate() {
+            if states[end] {
+                // Everything below a match is part of the matched subtree
+                return (Some(consumed), false);
+            }
+            let mut next = vec![false; end + 1];
+            for (pos, element) in self.elements.iter().enumerate() {
+                if !states[pos] {
+                    continue;
+                }
+                match element {
+                    PatternElement::Section(id) if *id == section.id => next[pos + 1] = true,
+                    PatternElement::Section(_) => {}
+                    PatternElement::AnySection => next[pos + 1] = true,
+                    PatternElement::AnyDepth => next[pos] = true,
+                }
+            }
+            self.close(&mut next);
+            if !next.iter().any(|s| *s) {
+                return (None, false);
+            }
+            states = next;
+        }
+
+        (
+            states[end].then_some(topic.sections.len()),
+            states[..end].iter().any(|s| *s),
+        )
+    }
+
+    /// `**` may match zero sections, so reaching it also reaches the element after it
+    fn close(&self, states: &mut [bool]) {
+        for (pos, element) in self.elements.iter().enumerate() {
+            if states[pos] && *element == PatternElement::AnyDepth {
+                states[pos + 1] = true;
+            }
+        }
+    }
+
+    /// True if `topic` matches the pattern or lies below a match
+    pub fn matches<T: TopicKeyProvider>(&self, topic: &T) -> bool {
+        self.run(topic.key()).0.is_some()
+    }
+
+    /// The part of `topic` below the shortest prefix matching the pattern, the pattern
+    /// equivalent of `TopicKey::remove_prefix`
+    pub fn strip_match<T: TopicKeyProvider>(&self, topic: &T) -> Option<TopicKey> {
+        let len = self.run(topic.key()).0?;
+        Some(TopicKey::from_existing(&topic.key().sections[len..]))
+    }
+
+    /// True if `topic` matches, lies below a match, or is a parent of a possible match.
+    /// This is the pattern equivalent of `TopicKey::matches`.
+    pub fn overlaps<T: TopicKeyProvider>(&self, topic: &T) -> bool {
+        let (matched, remaining) = self.run(topic.key());
+        matched.is_some() || remaining
+    }
+}
+
+impl From<&str> for TopicPattern {
+    fn from(value: &str) -> Self {
+        TopicPattern::new(&TopicKey::from_str(value))
+    }
+}
+
+impl From<&TopicKey> for TopicPattern {
+    fn from(value: &TopicKey) -> Self {
+        TopicPattern::new(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, topic: &str) -> bool {
+        TopicPattern::from(pattern).matches(&TopicKey::from_str(topic))
+    }
+
+    #[test]
+    fn test_pattern_single_wildcard() {
+        assert!(matches("robots/*/pose/x", "robots/a/pose/x"));
+        assert!(matches("robots/*/pose/x", "robots/b/pose/x"));
+        assert!(matches("robots/*/pose", "robots/a/pose/x"));
+        assert!(!matches("robots/*/pose/x", "robots/pose/x"));
+        assert!(!matches("robots/*/pose/x", "robots/a/b/pose/x"));
+        assert!(!matches("robots/*/pose/x", "robots/a/pose/y"));
+    }
+
+    #[test]
+    fn test_pattern_depth_wildcard() {
+        assert!(matches("sensors/**/temperature", "sensors/temperature"));
+        assert!(matches("sensors/**/temperature", "sensors/a/temperature"));
+        assert!(matches(
+            "sensors/**/temperature",
+            "sensors/a/b/c/temperature"
+        ));
+        assert!(!matches("sensors/**/temperature", "sensors/a/b/pressure"));
+        assert!(!matches("sensors/**/temperature", "other/a/temperature"));
+        assert!(matches("**/x", "robots/a/pose/x"));
+        assert!(matches("**", "anything/at/all"));
+    }
+
+    #[test]
+    fn test_pattern_literal() {
+        let pattern = TopicPattern::from("robots/a");
+        assert!(pattern.is_literal());
+        assert!(pattern.matches(&TopicKey::from_str("robots/a")));
+        assert!(pattern.matches(&TopicKey::from_str("robots/a/pose")));
+        assert!(!pattern.matches(&TopicKey::from_str("robots")));
+        assert!(!pattern.matches(&TopicKey::from_str("robots/b")));
+
+        let pattern = TopicPattern::from("robots/*/pose");
+        assert!(!pattern.is_literal());
+        assert_eq!(pattern.literal_prefix().display_name(), "robots");
+    }
+
+    #[test]
+    fn test_pattern_strip_match() {
+        let strip = |pattern: &str, topic: &str| {
+            TopicPattern::from(pattern)
+                .strip_match(&TopicKey::from_str(topic))
+                .map(|k| k.display_name())
+        };
+        assert_eq!(strip("robots/*/pose", "robots/a/pose/x"), Some("x".into()));
+        assert_eq!(strip("robots/a", "robots/a/pose/x"), Some("pose/x".into()));
+        assert_eq!(strip("**/pose", "robots/a/pose/x"), Some("x".into()));
+        assert_eq!(strip("robots/**", "robots/a/pose"), Some("a/pose".into()));
+        assert_eq!(strip("robots/*/pose", "robots/a/twist/x"), None);
+    }
+
+    #[test]
+    fn test_pattern_overlaps() {
+        let pattern = TopicPattern::from("robots/*/pose");
+        assert!(pattern.overlaps(&TopicKey::from_str("robots")));
+        assert!(pattern.overlaps(&TopicKey::from_str("robots/a")));
+        assert!(pattern.overlaps(&TopicKey::from_str("robots/a/pose/x")));
+        assert!(!pattern.overlaps(&TopicKey::from_str("robots/a/twist")));
+        assert!(!pattern.overlaps(&TopicKey::from_str("sensors")));
+    }
+}
@@ -0,0 +1,298 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    hash::Hasher,
+    sync::{Arc, RwLock},
+};
+
+use lazy_static::lazy_static;
+
+use super::{TopicIDType, TopicKey, TopicKeyProvider};
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// 64-bit FNV-1a. Unlike `DefaultHasher` it gives the same ids on every platform and
+/// Rust version, so ids hashed in different processes agree.
+#[derive(Debug, Clone, Copy)]
+pub struct StableHasher(u64);
+
+impl Default for StableHasher {
+    fn default() -> Self {
+        StableHasher(FNV_OFFSET)
+    }
+}
+
+impl Hasher for StableHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        self.write(&value.to_le_bytes());
+    }
+}
+
+pub fn stable_hash(name: &str) -> TopicIDType {
+    let mut hasher = StableHasher::default();
+    hasher.write(name.as_bytes());
+    hasher.finish()
+}
+
+/// Two different keys that hashed to the same id. The later key was given `assigned`,
+/// the next free id, so the two never compare equal. Key ids only live within the process,
+/// so probing is safe here, unlike for section ids.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TopicCollision {
+    pub id: TopicIDType,
+    pub existing: String,
+    pub name: String,
+    pub assigned: TopicIDType,
+}
+
+/// Interns section names and full keys, mapping ids back to what they were made from.
+///
+/// Section ids are always the stable hash of the name, so every process, snapshot and WAL
+/// agrees on them. Two section names with the same hash panic instead of moving one of them
+/// to another id, which would depend on registration order.
+#[derive(Default)]
+pub struct TopicRegistry {
+    sections: HashMap<TopicIDType, Arc<str>>,
+    section_ids: HashMap<Arc<str>, TopicIDType>,
+    keys: HashMap<TopicIDType, &'static TopicKey>,
+    key_ids: HashMap<&'static TopicKey, TopicIDType>,
+    collisions: Vec<TopicCollision>,
+}
+
+impl TopicRegistry {
+    pub fn new() -> TopicRegistry {
+        TopicRegistry::default()
+    }
+
+    pub fn section_id(&self, name: &str) -> Option<TopicIDType> {
+        self.section_ids.get(name).copied()
+    }
+
+    pub fn section_name(&self, id: TopicIDType) -> Option<Arc<str>> {
+        self.sections.get(&id).cloned()
+    }
+
+    pub fn intern_section(&mut self, name: &str) -> TopicIDType {
+        self.intern_section_as(name, stable_hash(name))
+    }
+
+    fn intern_section_as(&mut self, name: &str, hash: TopicIDType) -> TopicIDType {
+        if let Some(id) = self.section_id(name) {
+            return id;
+        }
+        if let Some(existing) = self.sections.get(&hash) {
+            panic!(
+                "Topic section id {} collision between '{}' and '{}', rename one of them",
+                hash, existing, name
+            );
+        }
+        let name: Arc<str> = Arc::from(name);
+        self.sections.insert(hash, name.clone());
+        self.section_ids.insert(name, hash);
+        hash
+    }
+
+    pub fn key(&self, id: TopicId) -> Option<&'static TopicKey> {
+        self.keys.get(&id.0).copied()
+    }
+
+    /// Interned keys are kept for the life of the process
+    pub fn intern_key(&mut self, key: &TopicKey) -> TopicId {
+        self.intern_key_as(key, key.id())
+    }
+
+    fn intern_key_as(&mut self, key: &TopicKey, hash: TopicIDType) -> TopicId {
+        if let Some(id) = self.key_ids.get(key) {
+            return TopicId(*id);
+        }
+        let mut id = hash;
+        while self.keys.contains_key(&id) {
+            id = id.wrapping_add(1);
+        }
+        if id != hash {
+            let existing = self.keys[&hash].display_name();
+            self.record_collision(hash, existing, key.display_name(), id);
+        }
+        let key: &'static TopicKey = Box::leak(Box::new(key.clone()));
+        self.keys.insert(id, key);
+        self.key_ids.insert(key, id);
+        TopicId(id)
+    }
+
+    pub fn collisions(&self) -> &[TopicCollision] {
+        &self.collisions
+    }
+
+    fn record_collision(
+        &mut self,
+        id: TopicIDType,
+        existing: String,
+        name: String,
+        assigned: TopicIDType,
+    ) {
+        log::error!(
+            "Topic id {} collision between '{}' and '{}', '{}' moved to {}",
+            id,
+            existing,
+            name,
+            name,
+            assigned
+        );
+        self.collisions.push(TopicCollision {
+            id,
+            existing,
+            name,
+            assigned,
+        });
+    }
+}
+
+lazy_static! {
+    static ref REGISTRY: RwLock<TopicRegistry> = RwLock::new(TopicRegistry::new());
+}
+
+/// Id of a section name in the global registry, registering it on first use
+pub fn intern_section(name: &str) -> TopicIDType {
+    if let Some(id) = REGISTRY.read().unwrap().section_id(name) {
+        return id;
+    }
+    REGISTRY.write().unwrap().intern_section(name)
+}
+
+pub fn section_name(id: TopicIDType) -> Option<Arc<str>> {
+    REGISTRY.read().unwrap().section_name(id)
+}
+
+/// Key collisions seen by the global registry so far
+pub fn collisions() -> Vec<TopicCollision> {
+    REGISTRY.read().unwrap().collisions().to_vec()
+}
+
+/// A `Copy` handle to a `TopicKey` interned in the global registry.
+/// Ids are only meaningful within the process, send the `TopicKey` over the wire instead.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TopicId(TopicIDType);
+
+impl TopicId {
+    pub fn new<T: TopicKeyProvider>(topic: &T) -> TopicId {
+        let key = topic.key();
+        let registry = REGISTRY.read().unwrap();
+        if let Some(id) = registry.key_ids.get(key) {
+            return TopicId(*id);
+        }
+        drop(registry);
+        REGISTRY.write().unwrap().intern_key(key)
+    }
+
+    pub fn id(&self) -> TopicIDType {
+        self.0
+    }
+}
+
+impl TopicKeyProvider for TopicId {
+    fn key(&self) -> &TopicKey {
+        REGISTRY
+            .read()
+            .unwrap()
+            .key(*self)
+            .expect("TopicId is only created by interning its key")
+    }
+}
+
+impl fmt::Debug for TopicId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "TopicId({})", self.key().display_name())
+    }
+}
+
+impl fmt::Display for TopicId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.key().display_name())
+    }
+}
+
+impl From<&TopicKey> for TopicId {
+    fn from(value: &TopicKey) -> Self {
+        TopicId::new(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::topics::TopicKeySection;
+
+    use super::*;
+
+    #[test]
+    fn test_stable_hash() {
+        // Published FNV-1a 64 test vectors
+        assert_eq!(stable_hash(""), 0xcbf29ce484222325);
+        assert_eq!(stable_hash("a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(stable_hash("foobar"), 0x85944171f73967e8);
+        assert_eq!(
+            TopicKeySection::new_generate("foobar").id,
+            0x85944171f73967e8
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "collision between 'first' and 'second'")]
+    fn test_registry_section_collision() {
+        let mut registry = TopicRegistry::new();
+        assert_eq!(registry.intern_section_as("first", 7), 7);
+        assert_eq!(registry.intern_section_as("first", 7), 7);
+        registry.intern_section_as("second", 7);
+    }
+
+    #[test]
+    fn test_registry_key_collision() {
+        let mut registry = TopicRegistry::new();
+        let a = TopicKey::from_str("robot/a");
+        let b = TopicKey::from_str("robot/b");
+        let c = TopicKey::from_str("robot/c");
+        let id_a = registry.intern_key_as(&a, 1);
+        let id_b = registry.intern_key_as(&b, 1);
+        let id_c = registry.intern_key_as(&c, 1);
+        assert_eq!(registry.intern_key_as(&b, 1), id_b);
+        assert_ne!(id_a, id_b);
+        assert_eq!(id_c.id(), 3);
+        assert_eq!(registry.key(id_b), Some(&b));
+        assert_eq!(registry.collisions().len(), 2);
+        assert_eq!(
+            registry.collisions()[0],
+            TopicCollision {
+                id: 1,
+                existing: "robot/a".to_string(),
+                name: "robot/b".to_string(),
+                assigned: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn test_topic_id() {
+        let key = TopicKey::from_str("robot/arm/joint_1");
+        let id = TopicId::new(&key);
+        let copy = id;
+        assert_eq!(
+            copy,
+            TopicId::from(&TopicKey::from_str("robot/arm/joint_1"))
+        );
+        assert_ne!(id, TopicId::new(&TopicKey::from_str("robot/arm/joint_2")));
+        assert_eq!(id.key(), &key);
+        assert_eq!(id.to_string(), "robot/arm/joint_1");
+        assert_eq!(section_name(key.sections[1].id).as_deref(), Some("arm"));
+    }
+}
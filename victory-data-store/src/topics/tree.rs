@@ -0,0 +1,324 @@
+use std::collections::{HashMap, HashSet};
+
+use super::{
+    pattern::{PatternElement, TopicPattern},
+    TopicIDType, TopicKey, TopicKeyHandle, TopicKeyProvider,
+};
+
+#[derive(Debug, Clone)]
+struct TopicTreeNode<V> {
+    value: Option<(TopicKeyHandle, V)>,
+    children: HashMap<TopicIDType, TopicTreeNode<V>>,
+}
+
+impl<V> Default for TopicTreeNode<V> {
+    fn default() -> Self {
+        TopicTreeNode {
+            value: None,
+            children: HashMap::new(),
+        }
+    }
+}
+
+impl<V> TopicTreeNode<V> {
+    fn collect<'a>(&'a self, out: &mut Vec<(&'a TopicKeyHandle, &'a V)>) {
+        if let Some((key, value)) = &self.value {
+            out.push((key, value));
+        }
+        for child in self.children.values() {
+            child.collect(out);
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.value.is_none() && self.children.is_empty()
+    }
+}
+
+/// Prefix tree of values keyed by topic, one level per `TopicKeySection::id`.
+/// Subtree and pattern lookups only visit the branches that can match.
+#[derive(Debug, Clone)]
+pub struct TopicTree<V> {
+    root: TopicTreeNode<V>,
+    len: usize,
+}
+
+impl<V> Default for TopicTree<V> {
+    fn default() -> Self {
+        TopicTree::new()
+    }
+}
+
+impl<V> TopicTree<V> {
+    pub fn new() -> TopicTree<V> {
+        TopicTree {
+            root: TopicTreeNode::default(),
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn node(&self, topic: &TopicKey) -> Option<&TopicTreeNode<V>> {
+        let mut node = &self.root;
+        for section in topic.sections.iter() {
+            node = node.children.get(&section.id)?;
+        }
+        Some(node)
+    }
+
+    /// Insert a value, returning the previous value for the topic
+    pub fn insert<T: TopicKeyProvider>(&mut self, topic: &T, value: V) -> Option<V> {
+        let mut node = &mut self.root;
+        for section in topic.key().sections.iter() {
+            node = node.children.entry(section.id).or_default();
+        }
+        let previous = node.value.replace((topic.handle(), value));
+        if previous.is_none() {
+            self.len += 1;
+        }
+        previous.map(|(_, v)| v)
+    }
+
+    pub fn get<T: TopicKeyProvider>(&self, topic: &T) -> Option<&V> {
+        self.node(topic.key())
+            .and_then(|n| n.value.as_ref())
+            .map(|(_, v)| v)
+    }
+
+    pub fn get_mut<T: TopicKeyProvider>(&mut self, topic: &T) -> Option<&mut V> {
+        let mut node = &mut self.root;
+        for section in topic.key().sections.iter() {
+            node = node.children.get_mut(&section.id)?;
+        }
+        node.value.as_mut().map(|(_, v)| v)
+    }
+
+    /// Remove a value, pruning any branches left empty
+    pub fn remove<T: TopicKeyProvider>(&mut self, topic: &T) -> Option<V> {
+        fn remove_from<V>(
+            node: &mut TopicTreeNode<V>,
+            sections: &[TopicIDType],
+        ) -> Option<(TopicKeyHandle, V)> {
+            match sections.split_first() {
+                None => node.value.take(),
+                Some((id, rest)) => {
+                    let child = node.children.get_mut(id)?;
+                    let removed = remove_from(child, rest);
+                    if child.is_empty() {
+                        node.children.remove(id);
+                    }
+                    removed
+                }
+            }
+        }
+
+        let sections: Vec<TopicIDType> = topic.key().sections.iter().map(|s| s.id).collect();
+        let removed = remove_from(&mut self.root, &sections);
+        if removed.is_some() {
+            self.len -= 1;
+        }
+        removed.map(|(_, v)| v)
+    }
+
+    /// Keep only the entries for which `f` returns true, pruning any branches left empty
+    pub fn retain<F: FnMut(&TopicKeyHandle, &mut V) -> bool>(&mut self, mut f: F) {
+        fn retain_node<V, F: FnMut(&TopicKeyHandle, &mut V) -> bool>(
+            node: &mut TopicTreeNode<V>,
+            f: &mut F,
+        ) -> usize {
+            let mut removed = 0;
+            if let Some((key, value)) = &mut node.value {
+                if !f(key, value) {
+                    node.value = None;
+                    removed += 1;
+                }
+            }
+            node.children.retain(|_, child| {
+                removed += retain_node(child, f);
+                !child.is_empty()
+            });
+            removed
+        }
+
+        self.len -= retain_node(&mut self.root, &mut f);
+    }
+
+    /// All entries in the tree
+    pub fn iter(&self) -> Vec<(&TopicKeyHandle, &V)> {
+        let mut out = Vec::with_capacity(self.len);
+        self.root.collect(&mut out);
+        out
+    }
+
+    /// Entries at or below `prefix`
+    pub fn get_subtree<T: TopicKeyProvider>(&self, prefix: &T) -> Vec<(&TopicKeyHandle, &V)> {
+        let mut out = Vec::new();
+        if let Some(node) = self.node(prefix.key()) {
+            node.collect(&mut out);
+        }
+        out
+    }
+
+    /// Entries whose topic is `topic`, one of its parents, or one of its children.
+    /// This is the tree equivalent of filtering with `TopicKey::matches`.
+    pub fn get_overlapping<T: TopicKeyProvider>(&self, topic: &T) -> Vec<(&TopicKeyHandle, &V)> {
+        let mut out = Vec::new();
+        let mut node = &self.root;
+        for section in topic.key().sections.iter() {
+            if let Some((key, value)) = &node.value {
+                out.push((key, value));
+            }
+            match node.children.get(&section.id) {
+                Some(child) => node = child,
+                None => return out,
+            }
+        }
+        node.collect(&mut out);
+        out
+    }
+
+    /// Entries at or below any match of `pattern`
+    pub fn get_matching(&self, pattern: &TopicPattern) -> Vec<(&TopicKeyHandle, &V)> {
+        let mut out = Vec::new();
+        let mut seen = HashSet::new();
+        Self::collect_matching(&self.root, pattern.elements(), &mut seen, &mut out);
+        out
+    }
+
+    fn collect_unseen<'a>(
+        node: &'a TopicTreeNode<V>,
+        seen: &mut HashSet<*const TopicTreeNode<V>>,
+        out: &mut Vec<(&'a TopicKeyHandle, &'a V)>,
+    ) {
+        if !seen.insert(node as *const _) {
+            return;
+        }
+        if let Some((key, value)) = &node.value {
+            out.push((key, value));
+        }
+        for child in node.children.values() {
+            Self::collect_unseen(child, seen, out);
+        }
+    }
+
+    fn collect_matching<'a>(
+        node: &'a TopicTreeNode<V>,
+        elements: &[PatternElement],
+        seen: &mut HashSet<*const TopicTreeNode<V>>,
+        out: &mut Vec<(&'a TopicKeyHandle, &'a V)>,
+    ) {
+        let Some((element, rest)) = elements.split_first() else {
+            // The whole subtree matches. With `**` the same subtree can be reached
+            // along several paths, so skip anything already collected.
+            Self::collect_unseen(node, seen, out);
+            return;
+        };
+
+        match element {
+            PatternElement::Section(id) => {
+                if let Some(child) = node.children.get(id) {
+                    Self::collect_matching(child, rest, seen, out);
+                }
+            }
+            PatternElement::AnySection => {
+                for child in node.children.values() {
+                    Self::collect_matching(child, rest, seen, out);
+                }
+            }
+            PatternElement::AnyDepth => {
+                Self::collect_matching(node, rest, seen, out);
+                for child in node.children.values() {
+                    Self::collect_matching(child, elements, seen, out);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree_of(topics: &[&str]) -> TopicTree<String> {
+        let mut tree = TopicTree::new();
+        for topic in topics {
+            tree.insert(&TopicKey::from_str(topic), topic.to_string());
+        }
+        tree
+    }
+
+    fn sorted(entries: Vec<(&TopicKeyHandle, &String)>) -> Vec<String> {
+        let mut values: Vec<String> = entries.into_iter().map(|(_, v)| v.clone()).collect();
+        values.sort();
+        values
+    }
+
+    #[test]
+    fn test_topic_tree_insert_remove() {
+        let mut tree = tree_of(&["a/b", "a/b/c", "a/d"]);
+        assert_eq!(tree.len(), 3);
+        assert_eq!(tree.get(&TopicKey::from_str("a/b")).unwrap(), "a/b");
+        assert!(tree.get(&TopicKey::from_str("a")).is_none());
+
+        let previous = tree.insert(&TopicKey::from_str("a/b"), "replaced".to_string());
+        assert_eq!(previous.unwrap(), "a/b");
+        assert_eq!(tree.len(), 3);
+
+        assert_eq!(tree.remove(&TopicKey::from_str("a/b/c")).unwrap(), "a/b/c");
+        assert!(tree.remove(&TopicKey::from_str("a/b/c")).is_none());
+        assert_eq!(tree.len(), 2);
+        assert_eq!(sorted(tree.iter()), vec!["a/d", "replaced"]);
+
+        tree.retain(|_, v| v.starts_with('r'));
+        assert_eq!(tree.len(), 1);
+        assert!(tree.get_subtree(&TopicKey::from_str("a/d")).is_empty());
+    }
+
+    #[test]
+    fn test_topic_tree_subtree_and_overlapping() {
+        let tree = tree_of(&["a", "a/b", "a/b/c", "a/d", "e/f"]);
+        assert_eq!(
+            sorted(tree.get_subtree(&TopicKey::from_str("a/b"))),
+            vec!["a/b", "a/b/c"]
+        );
+        assert_eq!(sorted(tree.get_subtree(&TopicKey::empty())).len(), 5);
+        assert!(tree.get_subtree(&TopicKey::from_str("x")).is_empty());
+
+        assert_eq!(
+            sorted(tree.get_overlapping(&TopicKey::from_str("a/b"))),
+            vec!["a", "a/b", "a/b/c"]
+        );
+        assert_eq!(
+            sorted(tree.get_overlapping(&TopicKey::from_str("a/b/c/z"))),
+            vec!["a", "a/b", "a/b/c"]
+        );
+    }
+
+    #[test]
+    fn test_topic_tree_matching() {
+        let tree = tree_of(&[
+            "robots/a/pose/x",
+            "robots/b/pose/x",
+            "robots/b/twist/x",
+            "x/y/x",
+        ]);
+        let matching = |pattern: &str| sorted(tree.get_matching(&TopicPattern::from(pattern)));
+        assert_eq!(
+            matching("robots/*/pose"),
+            vec!["robots/a/pose/x", "robots/b/pose/x"]
+        );
+        assert_eq!(
+            matching("robots/b"),
+            vec!["robots/b/pose/x", "robots/b/twist/x"]
+        );
+        // `x/y/x` is reachable through both `x` sections but only returned once
+        assert_eq!(matching("**/x").len(), 4);
+    }
+}
@@ -0,0 +1,151 @@
+use std::{fmt, marker::PhantomData};
+
+use serde::{
+    de::{self, DeserializeOwned, Visitor},
+    forward_to_deserialize_any, Deserializer, Serialize,
+};
+
+use super::{TopicKey, TopicKeyHandle, TopicKeyProvider};
+
+/// A topic that always holds a `T`, so reads and writes are type checked at compile time
+/// instead of failing to deserialize at runtime.
+pub struct TypedTopic<T: Serialize + DeserializeOwned> {
+    key: TopicKeyHandle,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T: Serialize + DeserializeOwned> TypedTopic<T> {
+    pub fn new<K: TopicKeyProvider>(topic: &K) -> TypedTopic<T> {
+        TypedTopic {
+            key: topic.handle(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// The `_type` marker written for `T`, None if `T` isn't a struct
+    pub fn struct_type(&self) -> Option<&'static str> {
+        struct_type_name::<T>()
+    }
+
+    /// Rust type name of `T`, used to tell apart registered types without a `_type` marker
+    pub fn type_name(&self) -> &'static str {
+        std::any::type_name::<T>()
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> TopicKeyProvider for TypedTopic<T> {
+    fn key(&self) -> &TopicKey {
+        &self.key
+    }
+
+    fn handle(&self) -> TopicKeyHandle {
+        self.key.clone()
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> Clone for TypedTopic<T> {
+    fn clone(&self) -> Self {
+        TypedTopic {
+            key: self.key.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> fmt::Debug for TypedTopic<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "TypedTopic<{}>({})",
+            std::any::type_name::<T>(),
+            self.key.display_name()
+        )
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> From<&str> for TypedTopic<T> {
+    fn from(value: &str) -> Self {
+        TypedTopic::new(&TopicKey::from_str(value))
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> From<&TopicKey> for TypedTopic<T> {
+    fn from(value: &TopicKey) -> Self {
+        TypedTopic::new(value)
+    }
+}
+
+/// Serde name of `T` if it deserializes as a struct, matching the `_type` marker
+/// `to_map` stores. Found by starting a deserialize that stops at `deserialize_struct`.
+pub fn struct_type_name<T: DeserializeOwned>() -> Option<&'static str> {
+    let mut name = None;
+    let _ = T::deserialize(NameProbe(&mut name));
+    name
+}
+
+struct NameProbe<'a>(&'a mut Option<&'static str>);
+
+#[derive(Debug)]
+struct ProbeDone;
+
+impl fmt::Display for ProbeDone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Type probe finished")
+    }
+}
+
+impl std::error::Error for ProbeDone {}
+
+impl de::Error for ProbeDone {
+    fn custom<M: fmt::Display>(_msg: M) -> Self {
+        ProbeDone
+    }
+}
+
+impl<'de> Deserializer<'de> for NameProbe<'_> {
+    type Error = ProbeDone;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, ProbeDone> {
+        Err(ProbeDone)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        _fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, ProbeDone> {
+        *self.0 = Some(name);
+        Err(ProbeDone)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        option unit unit_struct newtype_struct seq tuple tuple_struct map enum identifier
+        ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize)]
+    struct Pose {
+        x: f64,
+        y: f64,
+    }
+
+    #[test]
+    fn test_struct_type_name() {
+        assert_eq!(struct_type_name::<Pose>(), Some("Pose"));
+        assert_eq!(struct_type_name::<u64>(), None);
+        assert_eq!(struct_type_name::<Vec<Pose>>(), None);
+
+        let topic: TypedTopic<Pose> = "robot/pose".into();
+        assert_eq!(topic.struct_type(), Some("Pose"));
+        assert_eq!(topic.key().display_name(), "robot/pose");
+    }
+}
@@ -82,6 +82,16 @@ impl Add<Timespan> for Timepoint {
     }
 }
 
+/// Subtract operation.
+/// Timepoint - Timespan = Timepoint, saturating at zero
+impl Sub<Timespan> for Timepoint {
+    type Output = Timepoint;
+
+    fn sub(self, rhs: Timespan) -> Self::Output {
+        Timepoint::new_ns(self.ns().saturating_sub(rhs.ns()))
+    }
+}
+
 /// Subtract operation.
 /// Timepoint - Timepoint = Timespan
 impl Sub<Timepoint> for Timepoint {
@@ -183,6 +193,18 @@ mod tests_math {
         assert_eq!(c.time.nanos, 500_000_000);
     }
 
+    #[test]
+    fn test_sub_timespan() {
+        let a = Timepoint::new_secs(1.5);
+        let b = Timespan::new_secs(0.5);
+        let c = a.clone() - b;
+        assert_eq!(c.time.secs, 1);
+        assert_eq!(c.time.nanos, 0);
+
+        let d = a - Timespan::new_secs(2.0);
+        assert_eq!(d, Timepoint::zero());
+    }
+
     #[test]
     fn test_sub() {
         let a = Timepoint::new_secs(1.0);